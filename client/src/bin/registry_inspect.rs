@@ -0,0 +1,1102 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! Read-only inspection of the registry service database.
+//!
+//! Usage: registry-inspect [list|counts|export json [FILE]|export csv DIR]
+//!                         [--db PATH] [--config PATH] [--now TIMESTAMP_SEC] [--host-id PEER_ID]
+//!                         [--flagged]
+//!
+//! Keys, records and tombstones are flagged as expired by the service expiration rules
+//! and as invalid if they fail verification. Expiration timeouts and `accept_v1_signatures`
+//! are read from the service Config.toml if it's given, the service defaults are used otherwise.
+//! The database is the service one from `REGISTRY_DB_PATH` unless `--db` is given.
+//! Records of `--host-id` are never expired, like the service keeps records of its host
+
+use registry_client::key_transfer::{
+    get_effective_owner, verify_key_signature, verify_transfer_chain,
+};
+use registry_client::misc::{get_custom_option, get_db_path};
+use registry_client::{ExpiryPolicy, Key, KeyTransfer, Record, RecordMetadata, Tombstone};
+use rusqlite::types::FromSql;
+use rusqlite::{Connection, OpenFlags, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "Usage: registry-inspect [list|counts|export json [FILE]|export csv DIR] \
+                     [--db PATH] [--config PATH] [--now TIMESTAMP_SEC] [--host-id PEER_ID] \
+                     [--flagged]";
+
+/// Part of the service config used for expiration and verification, defaults are the service ones
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ServiceConfig {
+    #[serde(flatten)]
+    expiry: ExpiryPolicy,
+    /// enabled in existing configs without the field like in the service
+    #[serde(default = "accept_v1_signatures_of_existing_config")]
+    accept_v1_signatures: bool,
+}
+
+fn accept_v1_signatures_of_existing_config() -> bool {
+    true
+}
+
+enum Command {
+    List,
+    Counts,
+    ExportJson(Option<String>),
+    /// directory for keys.csv, records.csv, tombstones.csv and counts.csv
+    ExportCsv(String),
+}
+
+struct Options {
+    command: Command,
+    db_path: String,
+    config: ServiceConfig,
+    now: u64,
+    /// peer id of the service host, its records are not expired
+    host_id: Option<String>,
+    only_flagged: bool,
+}
+
+#[derive(Serialize)]
+struct KeyRow {
+    #[serde(flatten)]
+    key: Key,
+    timestamp_published: u64,
+    weight: u32,
+    /// current owner after the key transfer chain
+    transferred_to: Option<String>,
+    expired: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct RecordRow {
+    #[serde(flatten)]
+    record: Record,
+    weight: u32,
+    expired: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TombstoneRow {
+    #[serde(flatten)]
+    tombstone: Tombstone,
+    expired: bool,
+    error: Option<String>,
+}
+
+#[derive(Serialize, Default)]
+struct KeyCounts {
+    key_id: String,
+    label: String,
+    records: u64,
+    expired_records: u64,
+    tombstones: u64,
+    expired_tombstones: u64,
+    invalid: u64,
+}
+
+#[derive(Serialize)]
+struct Database {
+    keys: Vec<KeyRow>,
+    records: Vec<RecordRow>,
+    tombstones: Vec<TombstoneRow>,
+    counts: Vec<KeyCounts>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut positional = vec![];
+    let mut db_path = get_db_path();
+    let mut config = ServiceConfig::default();
+    let mut now = None;
+    let mut host_id = None;
+    let mut only_flagged = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--db" => db_path = args.next().ok_or("--db requires a value")?,
+            "--config" => {
+                let path = args.next().ok_or("--config requires a value")?;
+                let content =
+                    std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                config = toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            "--now" => {
+                let value = args.next().ok_or("--now requires a value")?;
+                now = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("invalid --now {}: {}", value, e))?,
+                );
+            }
+            "--host-id" => host_id = Some(args.next().ok_or("--host-id requires a value")?),
+            "--flagged" => only_flagged = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => positional.push(arg),
+        }
+    }
+
+    let positional: Vec<&str> = positional.iter().map(String::as_str).collect();
+    let command = match positional.as_slice() {
+        [] | ["list"] => Command::List,
+        ["counts"] => Command::Counts,
+        ["export", "json"] => Command::ExportJson(None),
+        ["export", "json", path] => Command::ExportJson(Some(path.to_string())),
+        ["export", "csv", dir] => Command::ExportCsv(dir.to_string()),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let now = match now {
+        Some(now) => now,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_secs(),
+    };
+
+    Ok(Options {
+        command,
+        db_path,
+        config,
+        now,
+        host_id,
+        only_flagged,
+    })
+}
+
+/// NULL is read as the default value
+fn get<T: FromSql + Default>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    Ok(row.get::<_, Option<T>>(index)?.unwrap_or_default())
+}
+
+fn get_u64(row: &Row, index: usize) -> rusqlite::Result<u64> {
+    Ok(get::<i64>(row, index)? as u64)
+}
+
+/// Check the key id and verify the key like the service does: its transfer chain
+/// link by link and the key signature by the owner at `timestamp_created`
+fn verify_key(key: &Key, transfers: &[KeyTransfer], options: &Options) -> Option<String> {
+    let expected_id = Key::get_id(&key.label, &key.owner_peer_id);
+    if key.id != expected_id {
+        return Some(format!(
+            "key id doesn't match label and owner_peer_id, expected {}",
+            expected_id
+        ));
+    }
+
+    key.verify_timestamp(options.now)
+        .and_then(|_| verify_transfer_chain(&key.id, &key.owner_peer_id, transfers, options.now))
+        .and_then(|_| verify_key_signature(key, transfers, options.config.accept_v1_signatures))
+        .err()
+        .map(|e| e.to_string())
+}
+
+/// Transfer chains by key id ordered by `timestamp_issued`
+fn read_key_transfers(
+    connection: &Connection,
+) -> rusqlite::Result<BTreeMap<String, Vec<KeyTransfer>>> {
+    let mut statement = connection.prepare(
+        "SELECT key_id, issued_by, new_owner_peer_id, timestamp_issued, signature \
+         FROM key_transfers_table ORDER BY key_id, timestamp_issued",
+    )?;
+    let mut rows = statement.query([])?;
+    let mut result: BTreeMap<String, Vec<KeyTransfer>> = BTreeMap::new();
+    while let Some(row) = rows.next()? {
+        let transfer = KeyTransfer {
+            key_id: get(row, 0)?,
+            issued_by: get(row, 1)?,
+            new_owner_peer_id: get(row, 2)?,
+            timestamp_issued: get_u64(row, 3)?,
+            signature: get(row, 4)?,
+        };
+        result
+            .entry(transfer.key_id.clone())
+            .or_default()
+            .push(transfer);
+    }
+
+    Ok(result)
+}
+
+/// `kept_records` are ids of keys with records which are not removed as expired
+fn read_keys(
+    connection: &Connection,
+    kept_records: &BTreeSet<&str>,
+    options: &Options,
+) -> rusqlite::Result<Vec<KeyRow>> {
+    let mut statement = connection.prepare(
+        "SELECT key_id, label, owner_peer_id, timestamp_created, challenge, challenge_type, \
+         signature, timestamp_published, weight \
+         FROM keys_table ORDER BY key_id",
+    )?;
+    let transfers = read_key_transfers(connection)?;
+    let mut rows = statement.query([])?;
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let key = Key {
+            id: row.get(0)?,
+            label: get(row, 1)?,
+            owner_peer_id: get(row, 2)?,
+            timestamp_created: get_u64(row, 3)?,
+            challenge: get(row, 4)?,
+            challenge_type: get(row, 5)?,
+            signature: get(row, 6)?,
+        };
+        let transfers = transfers.get(&key.id).map_or(&[][..], Vec::as_slice);
+
+        // keys are removed after `expired_timeout` if there are no records left after expired ones
+        let expired = options
+            .config
+            .expiry
+            .is_expired(key.timestamp_created, options.now)
+            && !kept_records.contains(key.id.as_str());
+        let error = verify_key(&key, transfers, options);
+        let transferred_to = (!transfers.is_empty()).then(|| get_effective_owner(&key, transfers));
+        result.push(KeyRow {
+            key,
+            timestamp_published: get_u64(row, 7)?,
+            weight: get_u64(row, 8)? as u32,
+            transferred_to,
+            expired,
+            error,
+        });
+    }
+
+    Ok(result)
+}
+
+fn read_records(connection: &Connection, options: &Options) -> rusqlite::Result<Vec<RecordRow>> {
+    let mut statement = connection.prepare(
+        "SELECT key_id, issued_by, peer_id, timestamp_issued, solution, issuer_signature, \
+         value, relay_id, service_id, timestamp_created, signature, weight, ttl \
+         FROM records_table WHERE is_tombstoned = 0 ORDER BY key_id, peer_id, issued_by",
+    )?;
+    let config = &options.config;
+    let mut rows = statement.query([])?;
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let record = Record {
+            metadata: RecordMetadata {
+                key_id: get(row, 0)?,
+                issued_by: get(row, 1)?,
+                peer_id: get(row, 2)?,
+                timestamp_issued: get_u64(row, 3)?,
+                solution: get(row, 4)?,
+                issuer_signature: get(row, 5)?,
+                value: get(row, 6)?,
+                relay_id: get_custom_option(get(row, 7)?),
+                service_id: get_custom_option(get(row, 8)?),
+                ttl: row
+                    .get::<_, Option<i64>>(12)?
+                    .map(|ttl| ttl as u64)
+                    .into_iter()
+                    .collect(),
+            },
+            timestamp_created: get_u64(row, 9)?,
+            signature: get(row, 10)?,
+        };
+
+        let is_host_record = options.host_id.as_ref() == Some(&record.metadata.peer_id);
+        let expired = !is_host_record
+            && config.expiry.is_record_expired(
+                record.timestamp_created,
+                &record.metadata.ttl,
+                options.now,
+            );
+        let error = record
+            .verify(options.now, config.accept_v1_signatures)
+            .err()
+            .map(|e| e.to_string());
+        result.push(RecordRow {
+            record,
+            weight: get_u64(row, 11)? as u32,
+            expired,
+            error,
+        });
+    }
+
+    Ok(result)
+}
+
+fn read_tombstones(
+    connection: &Connection,
+    options: &Options,
+) -> rusqlite::Result<Vec<TombstoneRow>> {
+    let mut statement = connection.prepare(
+        "SELECT key_id, issued_by, peer_id, timestamp_issued, solution, issuer_signature \
+         FROM records_table WHERE is_tombstoned = 1 ORDER BY key_id, peer_id, issued_by",
+    )?;
+    let config = &options.config;
+    let mut rows = statement.query([])?;
+    let mut result = vec![];
+    while let Some(row) = rows.next()? {
+        let tombstone = Tombstone {
+            key_id: get(row, 0)?,
+            issued_by: get(row, 1)?,
+            peer_id: get(row, 2)?,
+            timestamp_issued: get_u64(row, 3)?,
+            solution: get(row, 4)?,
+            issuer_signature: get(row, 5)?,
+        };
+
+        let expired = config
+            .expiry
+            .is_tombstone_expired(tombstone.timestamp_issued, options.now);
+        let error = tombstone
+            .verify(options.now, config.accept_v1_signatures)
+            .err()
+            .map(|e| e.to_string());
+        result.push(TombstoneRow {
+            tombstone,
+            expired,
+            error,
+        });
+    }
+
+    Ok(result)
+}
+
+fn key_counts<'a>(counts: &'a mut BTreeMap<String, KeyCounts>, key_id: &str) -> &'a mut KeyCounts {
+    counts
+        .entry(key_id.to_string())
+        .or_insert_with(|| KeyCounts {
+            key_id: key_id.to_string(),
+            ..Default::default()
+        })
+}
+
+/// Counts per key id, records and tombstones of missing keys are counted too
+fn count(keys: &[KeyRow], records: &[RecordRow], tombstones: &[TombstoneRow]) -> Vec<KeyCounts> {
+    let mut counts = BTreeMap::new();
+    for row in keys {
+        let key_counts = key_counts(&mut counts, &row.key.id);
+        key_counts.label = row.key.label.clone();
+        key_counts.invalid += row.error.is_some() as u64;
+    }
+
+    for row in records {
+        let key_counts = key_counts(&mut counts, &row.record.metadata.key_id);
+        key_counts.records += 1;
+        key_counts.expired_records += row.expired as u64;
+        key_counts.invalid += row.error.is_some() as u64;
+    }
+
+    for row in tombstones {
+        let key_counts = key_counts(&mut counts, &row.tombstone.key_id);
+        key_counts.tombstones += 1;
+        key_counts.expired_tombstones += row.expired as u64;
+        key_counts.invalid += row.error.is_some() as u64;
+    }
+
+    counts.into_values().collect()
+}
+
+fn read_database(options: &Options) -> Result<Database, String> {
+    let connection =
+        Connection::open_with_flags(&options.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("{}: {}", options.db_path, e))?;
+    let read = || -> rusqlite::Result<Database> {
+        // like the service, expired records are removed before keys without records
+        let mut records = read_records(&connection, options)?;
+        let kept_records = records
+            .iter()
+            .filter(|row| !row.expired)
+            .map(|row| row.record.metadata.key_id.as_str())
+            .collect();
+        let mut keys = read_keys(&connection, &kept_records, options)?;
+        let mut tombstones = read_tombstones(&connection, options)?;
+        let counts = count(&keys, &records, &tombstones);
+
+        if options.only_flagged {
+            keys.retain(|row| row.expired || row.error.is_some());
+            records.retain(|row| row.expired || row.error.is_some());
+            tombstones.retain(|row| row.expired || row.error.is_some());
+        }
+
+        Ok(Database {
+            keys,
+            records,
+            tombstones,
+            counts,
+        })
+    };
+
+    read().map_err(|e| format!("{}: {}", options.db_path, e))
+}
+
+fn flags(expired: bool, error: &Option<String>) -> String {
+    let mut flags = String::new();
+    if expired {
+        flags.push_str(" EXPIRED");
+    }
+    if let Some(error) = error {
+        flags.push_str(&format!(" INVALID: {}", error));
+    }
+    flags
+}
+
+fn print_list(database: &Database) {
+    println!("keys: {}", database.keys.len());
+    for row in &database.keys {
+        let key = &row.key;
+        let transferred_to = row
+            .transferred_to
+            .as_ref()
+            .map(|peer_id| format!(" transferred_to={}", peer_id))
+            .unwrap_or_default();
+        println!(
+            "  {} label={} owner={} created={}{}{}",
+            key.id,
+            key.label,
+            key.owner_peer_id,
+            key.timestamp_created,
+            transferred_to,
+            flags(row.expired, &row.error)
+        );
+    }
+
+    println!("records: {}", database.records.len());
+    for row in &database.records {
+        let metadata = &row.record.metadata;
+        println!(
+            "  {} issued_by={} peer_id={} value={} created={} ttl={:?}{}",
+            metadata.key_id,
+            metadata.issued_by,
+            metadata.peer_id,
+            metadata.value,
+            row.record.timestamp_created,
+            metadata.ttl,
+            flags(row.expired, &row.error)
+        );
+    }
+
+    println!("tombstones: {}", database.tombstones.len());
+    for row in &database.tombstones {
+        let tombstone = &row.tombstone;
+        println!(
+            "  {} issued_by={} peer_id={} issued={}{}",
+            tombstone.key_id,
+            tombstone.issued_by,
+            tombstone.peer_id,
+            tombstone.timestamp_issued,
+            flags(row.expired, &row.error)
+        );
+    }
+}
+
+fn print_counts(counts: &[KeyCounts]) {
+    println!("key_id\tlabel\trecords\texpired_records\ttombstones\texpired_tombstones\tinvalid");
+    for c in counts {
+        println!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            c.key_id,
+            c.label,
+            c.records,
+            c.expired_records,
+            c.tombstones,
+            c.expired_tombstones,
+            c.invalid
+        );
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_csv(
+    dir: &Path,
+    name: &str,
+    header: &[&str],
+    rows: Vec<Vec<String>>,
+) -> Result<(), String> {
+    let mut content = header.join(",");
+    content.push('\n');
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+        content.push_str(&fields.join(","));
+        content.push('\n');
+    }
+
+    let path = dir.join(name);
+    std::fs::write(&path, content).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Binary fields are exported in base58, lists are joined with `;`
+fn export_csv(database: &Database, dir: &str) -> Result<(), String> {
+    let dir = Path::new(dir);
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    let b58 = |bytes: &[u8]| bs58::encode(bytes).into_string();
+    let error = |error: &Option<String>| error.clone().unwrap_or_default();
+
+    write_csv(
+        dir,
+        "keys.csv",
+        &[
+            "key_id",
+            "label",
+            "owner_peer_id",
+            "timestamp_created",
+            "challenge",
+            "challenge_type",
+            "signature",
+            "timestamp_published",
+            "weight",
+            "transferred_to",
+            "expired",
+            "error",
+        ],
+        database
+            .keys
+            .iter()
+            .map(|row| {
+                let key = &row.key;
+                vec![
+                    key.id.clone(),
+                    key.label.clone(),
+                    key.owner_peer_id.clone(),
+                    key.timestamp_created.to_string(),
+                    b58(&key.challenge),
+                    key.challenge_type.clone(),
+                    b58(&key.signature),
+                    row.timestamp_published.to_string(),
+                    row.weight.to_string(),
+                    row.transferred_to.clone().unwrap_or_default(),
+                    row.expired.to_string(),
+                    error(&row.error),
+                ]
+            })
+            .collect(),
+    )?;
+
+    write_csv(
+        dir,
+        "records.csv",
+        &[
+            "key_id",
+            "issued_by",
+            "peer_id",
+            "timestamp_issued",
+            "solution",
+            "value",
+            "relay_id",
+            "service_id",
+            "issuer_signature",
+            "ttl",
+            "timestamp_created",
+            "signature",
+            "weight",
+            "expired",
+            "error",
+        ],
+        database
+            .records
+            .iter()
+            .map(|row| {
+                let metadata = &row.record.metadata;
+                vec![
+                    metadata.key_id.clone(),
+                    metadata.issued_by.clone(),
+                    metadata.peer_id.clone(),
+                    metadata.timestamp_issued.to_string(),
+                    b58(&metadata.solution),
+                    metadata.value.clone(),
+                    metadata.relay_id.join(";"),
+                    metadata.service_id.join(";"),
+                    b58(&metadata.issuer_signature),
+                    metadata
+                        .ttl
+                        .iter()
+                        .map(u64::to_string)
+                        .collect::<Vec<_>>()
+                        .join(";"),
+                    row.record.timestamp_created.to_string(),
+                    b58(&row.record.signature),
+                    row.weight.to_string(),
+                    row.expired.to_string(),
+                    error(&row.error),
+                ]
+            })
+            .collect(),
+    )?;
+
+    write_csv(
+        dir,
+        "tombstones.csv",
+        &[
+            "key_id",
+            "issued_by",
+            "peer_id",
+            "timestamp_issued",
+            "solution",
+            "issuer_signature",
+            "expired",
+            "error",
+        ],
+        database
+            .tombstones
+            .iter()
+            .map(|row| {
+                let tombstone = &row.tombstone;
+                vec![
+                    tombstone.key_id.clone(),
+                    tombstone.issued_by.clone(),
+                    tombstone.peer_id.clone(),
+                    tombstone.timestamp_issued.to_string(),
+                    b58(&tombstone.solution),
+                    b58(&tombstone.issuer_signature),
+                    row.expired.to_string(),
+                    error(&row.error),
+                ]
+            })
+            .collect(),
+    )?;
+
+    write_csv(
+        dir,
+        "counts.csv",
+        &[
+            "key_id",
+            "label",
+            "records",
+            "expired_records",
+            "tombstones",
+            "expired_tombstones",
+            "invalid",
+        ],
+        database
+            .counts
+            .iter()
+            .map(|c| {
+                vec![
+                    c.key_id.clone(),
+                    c.label.clone(),
+                    c.records.to_string(),
+                    c.expired_records.to_string(),
+                    c.tombstones.to_string(),
+                    c.expired_tombstones.to_string(),
+                    c.invalid.to_string(),
+                ]
+            })
+            .collect(),
+    )
+}
+
+/// Objects are exported in the shape of the marine structs, so they can be checked with registry-verify
+fn export_json(database: &Database, path: &Option<String>) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(database).map_err(|e| e.to_string())?;
+    match path {
+        Some(path) => std::fs::write(path, json).map_err(|e| format!("{}: {}", path, e)),
+        None => {
+            println!("{}", json);
+            Ok(())
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let options = parse_options()?;
+    let database = read_database(&options)?;
+    match &options.command {
+        Command::List => print_list(&database),
+        Command::Counts => print_counts(&database.counts),
+        Command::ExportJson(path) => export_json(&database, path)?,
+        Command::ExportCsv(dir) => export_csv(&database, dir)?,
+    }
+
+    Ok(())
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_keypair::KeyPair;
+
+    /// Tables of the service database after all migrations
+    const SCHEMA: &str = "
+        CREATE TABLE keys_table (
+            key_id TEXT PRIMARY KEY,
+            label TEXT,
+            owner_peer_id TEXT,
+            timestamp_created INTEGER,
+            challenge BLOB,
+            challenge_type TEXT,
+            signature BLOB NOT NULL,
+            timestamp_published INTEGER,
+            weight INTEGER,
+            transfer_issued_by TEXT,
+            transfer_new_owner_peer_id TEXT,
+            transfer_timestamp_issued INTEGER,
+            transfer_signature BLOB,
+            records_limit INTEGER,
+            digest BLOB,
+            digest_valid_until INTEGER
+        );
+        CREATE TABLE records_table (
+            key_id TEXT,
+            issued_by TEXT,
+            peer_id TEXT,
+            timestamp_issued INTEGER NOT NULL,
+            solution BLOB,
+            issuer_signature BLOB NOT NULL,
+            is_tombstoned INTEGER NOT NULL,
+            value TEXT,
+            relay_id TEXT,
+            service_id TEXT,
+            timestamp_created INTEGER,
+            signature BLOB,
+            weight INTEGER,
+            ttl INTEGER,
+            PRIMARY KEY (key_id, issued_by, peer_id)
+        );
+        CREATE TABLE key_transfers_table (
+            key_id TEXT,
+            issued_by TEXT,
+            new_owner_peer_id TEXT,
+            timestamp_issued INTEGER NOT NULL,
+            signature BLOB NOT NULL,
+            PRIMARY KEY (key_id, timestamp_issued)
+        );
+    ";
+
+    fn options(db_path: String, host_id: Option<String>, only_flagged: bool) -> Options {
+        Options {
+            command: Command::List,
+            db_path,
+            config: ServiceConfig {
+                expiry: ExpiryPolicy {
+                    expired_timeout: 50,
+                    max_record_ttl: 1000,
+                    tombstone_retention: 50,
+                    ..Default::default()
+                },
+                accept_v1_signatures: false,
+            },
+            now: 200,
+            host_id,
+            only_flagged,
+        }
+    }
+
+    fn insert_key(connection: &Connection, kp: &KeyPair, label: &str) -> String {
+        insert_key_signed_by(connection, &kp.get_peer_id().to_base58(), kp, label)
+    }
+
+    /// insert the key originally owned by `owner_peer_id`, signed by `signer_kp`
+    fn insert_key_signed_by(
+        connection: &Connection,
+        owner_peer_id: &str,
+        signer_kp: &KeyPair,
+        label: &str,
+    ) -> String {
+        let mut key = Key::new(
+            label.to_string(),
+            owner_peer_id.to_string(),
+            100,
+            vec![],
+            "".to_string(),
+            vec![],
+        );
+        key.sign(signer_kp).unwrap();
+        connection
+            .execute(
+                "INSERT INTO keys_table (key_id, label, owner_peer_id, timestamp_created, \
+                 challenge, challenge_type, signature, timestamp_published, weight) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 100, 0)",
+                rusqlite::params![
+                    key.id,
+                    key.label,
+                    key.owner_peer_id,
+                    key.timestamp_created as i64,
+                    key.challenge,
+                    key.challenge_type,
+                    key.signature
+                ],
+            )
+            .unwrap();
+        key.id
+    }
+
+    fn insert_record(
+        connection: &Connection,
+        key_id: &str,
+        kp: &KeyPair,
+        host_kp: &KeyPair,
+        ttl: u64,
+    ) {
+        let mut metadata = RecordMetadata {
+            key_id: key_id.to_string(),
+            issued_by: kp.get_peer_id().to_base58(),
+            peer_id: host_kp.get_peer_id().to_base58(),
+            timestamp_issued: 100,
+            value: "value".to_string(),
+            ttl: vec![ttl],
+            ..Default::default()
+        };
+        metadata.sign(kp).unwrap();
+        let mut record = Record {
+            metadata,
+            timestamp_created: 100,
+            ..Default::default()
+        };
+        record.sign(host_kp).unwrap();
+
+        let metadata = record.metadata;
+        connection
+            .execute(
+                "INSERT INTO records_table VALUES \
+                 (?1, ?2, ?3, ?4, ?5, ?6, 0, ?7, '', '', ?8, ?9, 0, ?10)",
+                rusqlite::params![
+                    metadata.key_id,
+                    metadata.issued_by,
+                    metadata.peer_id,
+                    metadata.timestamp_issued as i64,
+                    metadata.solution,
+                    metadata.issuer_signature,
+                    metadata.value,
+                    record.timestamp_created as i64,
+                    record.signature,
+                    ttl as i64
+                ],
+            )
+            .unwrap();
+    }
+
+    fn insert_transfer(
+        connection: &Connection,
+        key_id: &str,
+        kp: &KeyPair,
+        new_owner_kp: &KeyPair,
+        timestamp: u64,
+    ) {
+        let mut transfer = KeyTransfer {
+            key_id: key_id.to_string(),
+            issued_by: kp.get_peer_id().to_base58(),
+            new_owner_peer_id: new_owner_kp.get_peer_id().to_base58(),
+            timestamp_issued: timestamp,
+            ..Default::default()
+        };
+        transfer.sign(kp).unwrap();
+        connection
+            .execute(
+                "INSERT INTO key_transfers_table VALUES (?1, ?2, ?3, ?4, ?5)",
+                rusqlite::params![
+                    transfer.key_id,
+                    transfer.issued_by,
+                    transfer.new_owner_peer_id,
+                    transfer.timestamp_issued as i64,
+                    transfer.signature
+                ],
+            )
+            .unwrap();
+    }
+
+    fn insert_tombstone(connection: &Connection, key_id: &str, kp: &KeyPair, timestamp: u64) {
+        let mut tombstone = Tombstone {
+            key_id: key_id.to_string(),
+            issued_by: kp.get_peer_id().to_base58(),
+            peer_id: kp.get_peer_id().to_base58(),
+            timestamp_issued: timestamp,
+            ..Default::default()
+        };
+        tombstone.sign(kp).unwrap();
+        connection
+            .execute(
+                "INSERT INTO records_table \
+                 (key_id, issued_by, peer_id, timestamp_issued, solution, issuer_signature, is_tombstoned) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1)",
+                rusqlite::params![
+                    tombstone.key_id,
+                    tombstone.issued_by,
+                    tombstone.peer_id,
+                    tombstone.timestamp_issued as i64,
+                    tombstone.solution,
+                    tombstone.issuer_signature
+                ],
+            )
+            .unwrap();
+    }
+
+    /// Database with keys:
+    /// `live` with a live record and an expired tombstone,
+    /// `expired` with an expired record only,
+    /// `host` with an expired record of the host,
+    /// `invalid` with a broken signature and a live tombstone only
+    fn create_database(name: &str, kp: &KeyPair, host_kp: &KeyPair) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "registry-inspect-{}-{}.db",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let connection = Connection::open(&path).unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+
+        let other_kp = KeyPair::generate_ed25519();
+        let live = insert_key(&connection, kp, "live");
+        insert_record(&connection, &live, kp, &other_kp, 1000);
+        insert_tombstone(&connection, &live, kp, 100);
+        let expired = insert_key(&connection, kp, "expired");
+        insert_record(&connection, &expired, kp, &other_kp, 10);
+        let host = insert_key(&connection, kp, "host");
+        insert_record(&connection, &host, kp, host_kp, 10);
+        let invalid = insert_key(&connection, kp, "invalid");
+        connection
+            .execute(
+                "UPDATE keys_table SET signature = x'00' WHERE key_id = ?1",
+                [&invalid],
+            )
+            .unwrap();
+        insert_tombstone(&connection, &invalid, kp, 180);
+
+        path.to_string_lossy().to_string()
+    }
+
+    /// (label, expired, invalid) of the keys ordered by label
+    fn key_flags(database: &Database) -> Vec<(String, bool, bool)> {
+        let mut flags: Vec<_> = database
+            .keys
+            .iter()
+            .map(|row| (row.key.label.clone(), row.expired, row.error.is_some()))
+            .collect();
+        flags.sort();
+        flags
+    }
+
+    fn flags(label: &str, expired: bool, invalid: bool) -> (String, bool, bool) {
+        (label.to_string(), expired, invalid)
+    }
+
+    #[test]
+    fn database_rows_are_flagged() {
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let db_path = create_database("flagged", &kp, &host_kp);
+
+        let database = read_database(&options(db_path.clone(), None, false)).unwrap();
+        assert_eq!(
+            key_flags(&database),
+            [
+                flags("expired", true, false),
+                flags("host", true, false),
+                flags("invalid", true, true),
+                flags("live", false, false),
+            ]
+        );
+        let expired_records: Vec<bool> = database.records.iter().map(|r| r.expired).collect();
+        assert_eq!(expired_records.iter().filter(|e| **e).count(), 2);
+        assert!(database.records.iter().all(|r| r.error.is_none()));
+        let mut tombstones: Vec<(u64, bool)> = database
+            .tombstones
+            .iter()
+            .map(|t| (t.tombstone.timestamp_issued, t.expired))
+            .collect();
+        tombstones.sort();
+        assert_eq!(tombstones, [(100, true), (180, false)]);
+        assert_eq!(database.counts.len(), 4);
+        assert_eq!(database.counts.iter().map(|c| c.invalid).sum::<u64>(), 1);
+
+        // host records are kept by the service, and so are their keys
+        let host_id = Some(host_kp.get_peer_id().to_base58());
+        let database = read_database(&options(db_path.clone(), host_id, false)).unwrap();
+        assert!(key_flags(&database).contains(&flags("host", false, false)));
+        assert_eq!(database.records.iter().filter(|r| r.expired).count(), 1);
+
+        let database = read_database(&options(db_path.clone(), None, true)).unwrap();
+        assert_eq!(
+            key_flags(&database),
+            [
+                flags("expired", true, false),
+                flags("host", true, false),
+                flags("invalid", true, true),
+            ]
+        );
+        assert!(database.records.iter().all(|r| r.expired));
+        assert_eq!(database.records.len(), 2);
+        assert_eq!(database.tombstones.len(), 1);
+        assert!(database.tombstones[0].expired);
+        // counts are not filtered
+        assert_eq!(database.counts.len(), 4);
+
+        std::fs::remove_file(db_path).unwrap();
+    }
+
+    #[test]
+    fn keys_are_verified_by_transfer_chain() {
+        let kp = KeyPair::generate_ed25519();
+        let second_kp = KeyPair::generate_ed25519();
+        let third_kp = KeyPair::generate_ed25519();
+        let path = std::env::temp_dir().join(format!(
+            "registry-inspect-transfers-{}.db",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let db_path = path.to_string_lossy().to_string();
+        let connection = Connection::open(&path).unwrap();
+        connection.execute_batch(SCHEMA).unwrap();
+
+        // the key version created after two transfers is signed by the third owner
+        let owner_peer_id = kp.get_peer_id().to_base58();
+        let key_id = insert_key_signed_by(&connection, &owner_peer_id, &third_kp, "transferred");
+        insert_transfer(&connection, &key_id, &kp, &second_kp, 50);
+        insert_transfer(&connection, &key_id, &second_kp, &third_kp, 60);
+
+        let database = read_database(&options(db_path.clone(), None, false)).unwrap();
+        let row = &database.keys[0];
+        assert!(row.error.is_none(), "{:?}", row.error);
+        assert_eq!(row.transferred_to, Some(third_kp.get_peer_id().to_base58()));
+
+        // the second link can't be verified without the first one
+        connection
+            .execute(
+                "DELETE FROM key_transfers_table WHERE timestamp_issued = 50",
+                [],
+            )
+            .unwrap();
+        let database = read_database(&options(db_path.clone(), None, false)).unwrap();
+        let error = database.keys[0].error.clone().unwrap();
+        assert!(error.contains("is owned by"), "{}", error);
+
+        std::fs::remove_file(db_path).unwrap();
+    }
+
+    #[test]
+    fn csv_fields_are_quoted() {
+        assert_eq!(csv_field("value"), "value");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+        assert_eq!(csv_field("a\rb"), "\"a\rb\"");
+    }
+}
@@ -0,0 +1,503 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! Offline verification of registry keys, records and tombstones read as JSON.
+//!
+//! Usage: registry-verify <key|record|tombstone> [FILE] [--now TIMESTAMP_SEC] [--config PATH]
+//!                        [--accept-v1]
+//!
+//! FILE contains a single object or an array of objects in the shape of the marine structs,
+//! stdin is read if FILE is omitted. Every field and signature check is printed,
+//! the exit code is 1 if any object fails a check or verification.
+//! Records are checked for expiration by the timeouts of the service Config.toml if it's given,
+//! the service defaults are used otherwise
+
+use fluence_keypair::Signature;
+use registry_client::{extract_public_key, ClientError, ExpiryPolicy, Key, Record, Tombstone};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::io::Read;
+use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const USAGE: &str = "Usage: registry-verify <key|record|tombstone> [FILE] [--now TIMESTAMP_SEC] \
+                     [--config PATH] [--accept-v1]";
+
+struct Options {
+    kind: String,
+    path: Option<String>,
+    /// read from the service config, defaults are the service ones
+    expiry: ExpiryPolicy,
+    now: u64,
+    accept_v1: bool,
+}
+
+/// Result of a single check: description of the checked value or the reason of the failure
+type Check = (String, Result<String, String>);
+
+fn parse_options() -> Result<Options, String> {
+    let mut args = std::env::args().skip(1);
+    let mut kind = None;
+    let mut path = None;
+    let mut expiry = ExpiryPolicy::default();
+    let mut now = None;
+    let mut accept_v1 = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--now" => {
+                let value = args.next().ok_or("--now requires a value")?;
+                now = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("invalid --now {}: {}", value, e))?,
+                );
+            }
+            "--config" => {
+                let path = args.next().ok_or("--config requires a value")?;
+                let content =
+                    std::fs::read_to_string(&path).map_err(|e| format!("{}: {}", path, e))?;
+                expiry = toml::from_str(&content).map_err(|e| format!("{}: {}", path, e))?;
+            }
+            "--accept-v1" => accept_v1 = true,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ if kind.is_none() => kind = Some(arg),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument {}\n{}", arg, USAGE)),
+        }
+    }
+
+    let now = match now {
+        Some(now) => now,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| e.to_string())?
+            .as_secs(),
+    };
+
+    Ok(Options {
+        kind: kind.ok_or(USAGE)?,
+        path,
+        expiry,
+        now,
+        accept_v1,
+    })
+}
+
+fn read_objects(path: &Option<String>) -> Result<Vec<Value>, String> {
+    let content = match path {
+        Some(path) => std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => {
+            let mut content = String::new();
+            std::io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|e| e.to_string())?;
+            content
+        }
+    };
+
+    match serde_json::from_str(&content).map_err(|e| format!("invalid JSON: {}", e))? {
+        Value::Array(objects) => Ok(objects),
+        object => Ok(vec![object]),
+    }
+}
+
+fn check_timestamp(field: &str, timestamp: u64, now: u64) -> Check {
+    let result = if timestamp <= now {
+        Ok(format!("{} is not in the future", timestamp))
+    } else {
+        Err(format!("{} is in the future, now is {}", timestamp, now))
+    };
+
+    (field.to_string(), result)
+}
+
+/// Check which payload version `signature` is made for, v1 is accepted only with `accept_v1`
+fn check_signature(
+    field: &str,
+    signer: &str,
+    signature: &[u8],
+    bytes: &[u8],
+    v1_bytes: &[u8],
+    accept_v1: bool,
+) -> Check {
+    let result = extract_public_key(signer.to_string())
+        .map_err(|e| format!("signer {} is not a valid peer id: {}", signer, e))
+        .and_then(|pk| {
+            let signature = Signature::from_bytes(pk.get_key_format(), signature.to_vec());
+            let v1_ok = pk.verify(v1_bytes, &signature).is_ok();
+            match (pk.verify(bytes, &signature), v1_ok, accept_v1) {
+                (Ok(()), _, _) => Ok(format!("valid v2 signature by {}", signer)),
+                (Err(_), true, true) => Ok(format!("valid legacy v1 signature by {}", signer)),
+                (Err(_), true, false) => Err(format!(
+                    "legacy v1 signature by {}, rerun with --accept-v1 to accept it",
+                    signer
+                )),
+                (Err(e), false, _) => Err(format!(
+                    "not a signature by {} of either v2 or v1 payload: {}",
+                    signer, e
+                )),
+            }
+        });
+
+    (field.to_string(), result)
+}
+
+fn check_key(key: &Key, options: &Options) -> Vec<Check> {
+    let expected_id = Key::get_id(&key.label, &key.owner_peer_id);
+    let id = if key.id == expected_id {
+        Ok("matches label and owner_peer_id".to_string())
+    } else {
+        Err(format!(
+            "{} doesn't match label and owner_peer_id, expected {}",
+            key.id, expected_id
+        ))
+    };
+
+    vec![
+        ("id".to_string(), id),
+        check_timestamp("timestamp_created", key.timestamp_created, options.now),
+        check_signature(
+            "signature",
+            &key.owner_peer_id,
+            &key.signature,
+            &key.signature_bytes(),
+            &key.signature_bytes_v1(),
+            options.accept_v1,
+        ),
+    ]
+}
+
+fn check_record(record: &Record, options: &Options) -> Vec<Check> {
+    let metadata = &record.metadata;
+    let ttl = if metadata.ttl.len() > 1 || metadata.ttl.contains(&0) {
+        Err(format!(
+            "{:?} should be empty or a single positive value",
+            metadata.ttl
+        ))
+    } else {
+        Ok(format!("{:?}", metadata.ttl))
+    };
+
+    let expiry_policy = &options.expiry;
+    let expires_at = expiry_policy.record_expires_at(record.timestamp_created, &metadata.ttl);
+    let expiry =
+        if expiry_policy.is_record_expired(record.timestamp_created, &metadata.ttl, options.now) {
+            Err(format!("expired at {}, now is {}", expires_at, options.now))
+        } else {
+            Ok(format!("expires at {}", expires_at))
+        };
+
+    vec![
+        check_timestamp("timestamp_created", record.timestamp_created, options.now),
+        check_timestamp(
+            "metadata.timestamp_issued",
+            metadata.timestamp_issued,
+            options.now,
+        ),
+        ("metadata.ttl".to_string(), ttl),
+        ("expiry".to_string(), expiry),
+        check_signature(
+            "metadata.issuer_signature",
+            &metadata.issued_by,
+            &metadata.issuer_signature,
+            &metadata.signature_bytes(),
+            &metadata.signature_bytes_v1(),
+            options.accept_v1,
+        ),
+        check_signature(
+            "signature",
+            &metadata.peer_id,
+            &record.signature,
+            &record.signature_bytes(),
+            &record.signature_bytes_v1(),
+            options.accept_v1,
+        ),
+    ]
+}
+
+fn check_tombstone(tombstone: &Tombstone, options: &Options) -> Vec<Check> {
+    vec![
+        check_timestamp("timestamp_issued", tombstone.timestamp_issued, options.now),
+        check_signature(
+            "issuer_signature",
+            &tombstone.issued_by,
+            &tombstone.issuer_signature,
+            &tombstone.signature_bytes(),
+            &tombstone.signature_bytes_v1(),
+            options.accept_v1,
+        ),
+    ]
+}
+
+/// Print checks of every object followed by the result of its `verify`,
+/// return if all objects pass both
+fn verify_all<T, C, V>(objects: Vec<Value>, check: C, verify: V, options: &Options) -> bool
+where
+    T: DeserializeOwned,
+    C: Fn(&T, &Options) -> Vec<Check>,
+    V: Fn(&T, &Options) -> Result<(), ClientError>,
+{
+    let mut all_valid = true;
+    for (i, object) in objects.into_iter().enumerate() {
+        println!("{} #{}", options.kind, i);
+        let object: T = match serde_json::from_value(object) {
+            Ok(object) => object,
+            Err(e) => {
+                println!("  FAIL  invalid {}: {}", options.kind, e);
+                all_valid = false;
+                continue;
+            }
+        };
+
+        for (field, result) in check(&object, options) {
+            match result {
+                Ok(description) => println!("  ok    {}: {}", field, description),
+                Err(reason) => {
+                    println!("  FAIL  {}: {}", field, reason);
+                    all_valid = false;
+                }
+            }
+        }
+
+        match verify(&object, options) {
+            Ok(()) => println!("  verify: ok"),
+            Err(e) => {
+                println!("  verify: {}", e);
+                all_valid = false;
+            }
+        }
+    }
+
+    all_valid
+}
+
+fn run() -> Result<bool, String> {
+    let options = parse_options()?;
+    let objects = read_objects(&options.path)?;
+    match options.kind.as_str() {
+        "key" => Ok(verify_all(
+            objects,
+            check_key,
+            |key: &Key, options| key.verify(options.now, options.accept_v1),
+            &options,
+        )),
+        "record" => Ok(verify_all(
+            objects,
+            check_record,
+            |record: &Record, options| record.verify(options.now, options.accept_v1),
+            &options,
+        )),
+        "tombstone" => Ok(verify_all(
+            objects,
+            check_tombstone,
+            |tombstone: &Tombstone, options| tombstone.verify(options.now, options.accept_v1),
+            &options,
+        )),
+        other => Err(format!(
+            "unknown object type {}, expected key, record or tombstone\n{}",
+            other, USAGE
+        )),
+    }
+}
+
+fn main() {
+    match run() {
+        Ok(true) => {}
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fluence_keypair::KeyPair;
+    use registry_client::RecordMetadata;
+
+    fn options(now: u64, accept_v1: bool) -> Options {
+        Options {
+            kind: String::new(),
+            path: None,
+            expiry: ExpiryPolicy::default(),
+            now,
+            accept_v1,
+        }
+    }
+
+    /// Fields of the failed checks
+    fn failed(checks: Vec<Check>) -> Vec<String> {
+        checks
+            .into_iter()
+            .filter(|(_, result)| result.is_err())
+            .map(|(field, _)| field)
+            .collect()
+    }
+
+    fn sign(kp: &KeyPair, bytes: &[u8]) -> Vec<u8> {
+        kp.sign(bytes).unwrap().get_raw_signature().bytes
+    }
+
+    fn signed_key(kp: &KeyPair) -> Key {
+        let mut key = Key::new(
+            "some_key".to_string(),
+            kp.get_peer_id().to_base58(),
+            100,
+            vec![],
+            "".to_string(),
+            vec![],
+        );
+        key.sign(kp).unwrap();
+        key
+    }
+
+    fn signed_record(kp: &KeyPair) -> Record {
+        signed_record_with_ttl(kp, vec![])
+    }
+
+    fn signed_record_with_ttl(kp: &KeyPair, ttl: Vec<u64>) -> Record {
+        let mut metadata = RecordMetadata {
+            key_id: Key::get_id("some_key", &kp.get_peer_id().to_base58()),
+            issued_by: kp.get_peer_id().to_base58(),
+            peer_id: kp.get_peer_id().to_base58(),
+            timestamp_issued: 100,
+            value: "value".to_string(),
+            ttl,
+            ..Default::default()
+        };
+        metadata.sign(kp).unwrap();
+
+        let mut record = Record {
+            metadata,
+            timestamp_created: 100,
+            ..Default::default()
+        };
+        record.sign(kp).unwrap();
+        record
+    }
+
+    fn signed_tombstone(kp: &KeyPair) -> Tombstone {
+        let mut tombstone = Tombstone {
+            key_id: Key::get_id("some_key", &kp.get_peer_id().to_base58()),
+            issued_by: kp.get_peer_id().to_base58(),
+            peer_id: kp.get_peer_id().to_base58(),
+            timestamp_issued: 100,
+            ..Default::default()
+        };
+        tombstone.sign(kp).unwrap();
+        tombstone
+    }
+
+    #[test]
+    fn key_checks() {
+        let kp = KeyPair::generate_ed25519();
+        let mut key = signed_key(&kp);
+        assert!(failed(check_key(&key, &options(150, false))).is_empty());
+        assert_eq!(
+            failed(check_key(&key, &options(50, false))),
+            ["timestamp_created"]
+        );
+
+        key.signature = sign(&kp, &key.signature_bytes_v1());
+        assert!(failed(check_key(&key, &options(150, true))).is_empty());
+        let checks = check_key(&key, &options(150, false));
+        assert!(matches!(&checks[2], (_, Err(e)) if e.contains("--accept-v1")));
+        assert_eq!(failed(checks), ["signature"]);
+
+        key.label = "other_key".to_string();
+        assert_eq!(
+            failed(check_key(&key, &options(150, true))),
+            ["id", "signature"]
+        );
+    }
+
+    #[test]
+    fn record_checks() {
+        let kp = KeyPair::generate_ed25519();
+        let mut record = signed_record(&kp);
+        assert!(failed(check_record(&record, &options(150, false))).is_empty());
+        assert_eq!(
+            failed(check_record(&record, &options(50, false))),
+            ["timestamp_created", "metadata.timestamp_issued"]
+        );
+
+        let mut tampered = record.clone();
+        tampered.metadata.value = "other_value".to_string();
+        assert_eq!(
+            failed(check_record(&tampered, &options(150, false))),
+            ["metadata.issuer_signature", "signature"]
+        );
+
+        record.metadata.issuer_signature = sign(&kp, &record.metadata.signature_bytes_v1());
+        record.signature = sign(&kp, &record.signature_bytes_v1());
+        assert!(failed(check_record(&record, &options(150, true))).is_empty());
+        assert_eq!(
+            failed(check_record(&record, &options(150, false))),
+            ["metadata.issuer_signature", "signature"]
+        );
+    }
+
+    #[test]
+    fn expired_record_checks() {
+        let kp = KeyPair::generate_ed25519();
+        let record = signed_record(&kp);
+        let expires_at = 100 + ExpiryPolicy::default().expired_timeout;
+        assert!(failed(check_record(&record, &options(expires_at - 1, false))).is_empty());
+        assert_eq!(
+            failed(check_record(&record, &options(expires_at, false))),
+            ["expiry"]
+        );
+
+        let record = signed_record_with_ttl(&kp, vec![50]);
+        assert!(failed(check_record(&record, &options(149, false))).is_empty());
+        assert_eq!(
+            failed(check_record(&record, &options(150, false))),
+            ["expiry"]
+        );
+
+        // ttl is capped by max_record_ttl
+        let mut options = options(150, false);
+        options.expiry.max_record_ttl = 20;
+        let record = signed_record_with_ttl(&kp, vec![50]);
+        assert_eq!(failed(check_record(&record, &options)), ["expiry"]);
+    }
+
+    #[test]
+    fn tombstone_checks() {
+        let kp = KeyPair::generate_ed25519();
+        let mut tombstone = signed_tombstone(&kp);
+        assert!(failed(check_tombstone(&tombstone, &options(150, false))).is_empty());
+        assert_eq!(
+            failed(check_tombstone(&tombstone, &options(50, false))),
+            ["timestamp_issued"]
+        );
+
+        let mut tampered = tombstone.clone();
+        tampered.peer_id = "other_peer_id".to_string();
+        assert_eq!(
+            failed(check_tombstone(&tampered, &options(150, false))),
+            ["issuer_signature"]
+        );
+
+        tombstone.issuer_signature = sign(&kp, &tombstone.signature_bytes_v1());
+        assert!(failed(check_tombstone(&tombstone, &options(150, true))).is_empty());
+        assert_eq!(
+            failed(check_tombstone(&tombstone, &options(150, false))),
+            ["issuer_signature"]
+        );
+    }
+}
@@ -0,0 +1,27 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+//! Defaults shared by the registry service and the tools reading its files
+
+pub static CONFIG_FILE: &str = "/tmp/Config.toml";
+pub static DB_PATH: &str = "/tmp/registry.db";
+/// environment variables overriding `CONFIG_FILE` and `DB_PATH`, set in the module config
+pub static CONFIG_FILE_ENV: &str = "REGISTRY_CONFIG_FILE";
+pub static DB_PATH_ENV: &str = "REGISTRY_DB_PATH";
+pub static DEFAULT_EXPIRED_AGE: u64 = 24 * 60 * 60;
+pub static DEFAULT_STALE_AGE: u64 = 60 * 60;
+pub static DEFAULT_MAX_RECORD_TTL: u64 = 7 * 24 * 60 * 60;
+/// tombstones should outlive records they remove, otherwise the records can be republished back
+pub static DEFAULT_TOMBSTONE_RETENTION: u64 = DEFAULT_MAX_RECORD_TTL;
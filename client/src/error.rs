@@ -0,0 +1,63 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use fluence_keypair::error::{DecodingError, SigningError, VerificationError};
+use thiserror::Error as ThisError;
+
+#[derive(ThisError, Debug)]
+pub enum ClientError {
+    #[error("Invalid signature for key_id {0}; label {1} and peer_id {2}: {3}")]
+    InvalidKeySignature(String, String, String, #[source] VerificationError),
+    #[error("Invalid record metadata signature for key_id {0} and issued by {1}: {2}")]
+    InvalidRecordMetadataSignature(String, String, #[source] VerificationError),
+    #[error("Invalid record signature for key_id {0} and issued by {1}: {2}")]
+    InvalidRecordSignature(String, String, #[source] VerificationError),
+    #[error("Invalid tombstone signature for key_id {0} and issued by {1}: {2}")]
+    InvalidTombstoneSignature(String, String, #[source] VerificationError),
+    #[error("Key can't be registered in the future")]
+    InvalidKeyTimestamp,
+    #[error("Record metadata can't be issued in the future")]
+    InvalidRecordMetadataTimestamp,
+    #[error("Record can't be registered in the future")]
+    InvalidRecordTimestamp,
+    #[error("Tombstone can't be issued in the future")]
+    InvalidTombstoneTimestamp,
+    #[error("Key transfer can't be issued in the future")]
+    InvalidKeyTransferTimestamp,
+    #[error("Invalid key transfer signature for key_id {0} and issued by {1}: {2}")]
+    InvalidKeyTransferSignature(String, String, #[source] VerificationError),
+    #[error("Key transfer for key_id {0} is issued by {1}, but the key is owned by {2}")]
+    KeyTransferNotByOwner(String, String, String),
+    #[error("Newer key transfer for key_id {0} already exists")]
+    NewerKeyTransferExists(String),
+    #[error("Key transfer is issued for key_id {0}, but the key has id {1}")]
+    InvalidKeyTransferKeyId(String, String),
+    #[error("peer id parse error: {0}")]
+    PeerIdParseError(String),
+    #[error("public key extraction from peer id failed: {0}")]
+    PublicKeyExtractionError(String),
+    #[error("{0}")]
+    PublicKeyDecodeError(
+        #[from]
+        #[source]
+        DecodingError,
+    ),
+    #[error("Signing failed: {0}")]
+    SigningError(
+        #[from]
+        #[source]
+        SigningError,
+    ),
+}
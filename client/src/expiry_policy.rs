@@ -0,0 +1,87 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::defaults::{
+    DEFAULT_EXPIRED_AGE, DEFAULT_MAX_RECORD_TTL, DEFAULT_STALE_AGE, DEFAULT_TOMBSTONE_RETENTION,
+};
+
+/// Single source of expiration rules of the service and the tools reading its database.
+/// Fields are named as in the service Config.toml, so the policy can be read from it
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExpiryPolicy {
+    pub expired_timeout: u64,
+    pub stale_timeout: u64,
+    pub max_record_ttl: u64,
+    pub tombstone_retention: u64,
+}
+
+impl ExpiryPolicy {
+    /// Items with timestamps less than or equal to the returned one are expired
+    pub fn expired_timestamp(&self, current_timestamp_sec: u64) -> u64 {
+        current_timestamp_sec.saturating_sub(self.expired_timeout)
+    }
+
+    /// Keys published before or at the returned timestamp should be republished
+    pub fn stale_timestamp(&self, current_timestamp_sec: u64) -> u64 {
+        current_timestamp_sec.saturating_sub(self.stale_timeout)
+    }
+
+    /// Tombstones issued before or at the returned timestamp are expired
+    pub fn tombstone_expired_timestamp(&self, current_timestamp_sec: u64) -> u64 {
+        current_timestamp_sec.saturating_sub(self.tombstone_retention)
+    }
+
+    pub fn is_expired(&self, timestamp: u64, current_timestamp_sec: u64) -> bool {
+        timestamp <= self.expired_timestamp(current_timestamp_sec)
+    }
+
+    /// Lifetime of a record: ttl set by the issuer capped by `max_record_ttl`
+    /// or `expired_timeout` if ttl is not set
+    pub fn record_ttl(&self, ttl: &[u64]) -> u64 {
+        ttl.first()
+            .map_or(self.expired_timeout, |ttl| (*ttl).min(self.max_record_ttl))
+    }
+
+    /// Records created at `timestamp_created` expire at the returned timestamp
+    pub fn record_expires_at(&self, timestamp_created: u64, ttl: &[u64]) -> u64 {
+        timestamp_created.saturating_add(self.record_ttl(ttl))
+    }
+
+    pub fn is_record_expired(
+        &self,
+        timestamp_created: u64,
+        ttl: &[u64],
+        current_timestamp_sec: u64,
+    ) -> bool {
+        self.record_expires_at(timestamp_created, ttl) <= current_timestamp_sec
+    }
+
+    pub fn is_tombstone_expired(&self, timestamp_issued: u64, current_timestamp_sec: u64) -> bool {
+        timestamp_issued <= self.tombstone_expired_timestamp(current_timestamp_sec)
+    }
+}
+
+impl Default for ExpiryPolicy {
+    fn default() -> Self {
+        Self {
+            expired_timeout: DEFAULT_EXPIRED_AGE,
+            stale_timeout: DEFAULT_STALE_AGE,
+            max_record_ttl: DEFAULT_MAX_RECORD_TTL,
+            tombstone_retention: DEFAULT_TOMBSTONE_RETENTION,
+        }
+    }
+}
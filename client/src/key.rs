@@ -0,0 +1,153 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::error::ClientError;
+use crate::misc::extract_public_key;
+use crate::signing::{verify_signature, CanonicalEncoder, DOMAIN_KEY};
+use fluence_keypair::KeyPair;
+#[cfg(feature = "marine")]
+use marine_rs_sdk::marine;
+use sha2::{Digest, Sha256};
+
+#[cfg_attr(feature = "marine", marine)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Default, Clone)]
+pub struct Key {
+    /// base58-encoded sha256(concat(label, owner_peer_id))
+    pub id: String,
+    /// any unique string defined by the owner
+    pub label: String,
+    /// peer id in base58
+    pub owner_peer_id: String,
+    /// timestamp of creation in seconds
+    pub timestamp_created: u64,
+    /// challenge in bytes, its meaning depends on `challenge_type`
+    pub challenge: Vec<u8>,
+    /// defines who can publish records and tombstones: open (or empty), owner, allowlist, delegation
+    pub challenge_type: String,
+    /// encoded and hashed previous fields signed by `owner_peer_id`
+    pub signature: Vec<u8>,
+}
+
+impl Key {
+    pub fn new(
+        label: String,
+        owner_peer_id: String,
+        timestamp_created: u64,
+        challenge: Vec<u8>,
+        challenge_type: String,
+        signature: Vec<u8>,
+    ) -> Self {
+        let id = Self::get_id(&label, &owner_peer_id);
+
+        Self {
+            id,
+            label,
+            owner_peer_id,
+            timestamp_created,
+            challenge,
+            challenge_type,
+            signature,
+        }
+    }
+
+    pub fn get_id(label: &str, owner_peer_id: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(format!("{}{}", label, owner_peer_id).as_bytes());
+        bs58::encode(hasher.finalize()).into_string()
+    }
+
+    pub fn signature_bytes(&self) -> Vec<u8> {
+        CanonicalEncoder::new(DOMAIN_KEY)
+            .string(&self.label)
+            .string(&self.owner_peer_id)
+            .u64(self.timestamp_created)
+            .bytes(&self.challenge)
+            .string(&self.challenge_type)
+            .finish()
+    }
+
+    /// Legacy v1 payload, see `signing::verify_signature`
+    pub fn signature_bytes_v1(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        bytes.push(self.label.len() as u8);
+        bytes.extend(self.label.as_bytes());
+
+        bytes.push(self.owner_peer_id.len() as u8);
+        bytes.extend(self.owner_peer_id.as_bytes());
+
+        bytes.extend(self.timestamp_created.to_le_bytes());
+
+        bytes.push(self.challenge.len() as u8);
+        bytes.extend(&self.challenge);
+
+        bytes.push(self.challenge_type.len() as u8);
+        bytes.extend(self.challenge_type.as_bytes());
+
+        let mut hasher = Sha256::new();
+        hasher.update(bytes);
+        hasher.finalize().to_vec()
+    }
+
+    pub fn verify_timestamp(&self, current_timestamp_sec: u64) -> Result<(), ClientError> {
+        if self.timestamp_created > current_timestamp_sec {
+            return Err(ClientError::InvalidKeyTimestamp);
+        }
+
+        Ok(())
+    }
+
+    /// the signer is the original owner or the new one after key transfer
+    pub fn verify_signature_by(
+        &self,
+        signer_peer_id: &str,
+        accept_v1: bool,
+    ) -> Result<(), ClientError> {
+        let pk = extract_public_key(signer_peer_id.to_string())?;
+        verify_signature(
+            &pk,
+            &self.signature,
+            &self.signature_bytes(),
+            || self.signature_bytes_v1(),
+            accept_v1,
+        )
+        .map_err(|e| {
+            ClientError::InvalidKeySignature(
+                self.id.clone(),
+                self.label.clone(),
+                signer_peer_id.to_string(),
+                e,
+            )
+        })
+    }
+
+    /// Check the timestamp and the signature of the owner, signatures of v1 payloads are accepted
+    /// if `accept_v1` is set. The registry also accepts signatures of the new owner after key transfer
+    pub fn verify(&self, current_timestamp_sec: u64, accept_v1: bool) -> Result<(), ClientError> {
+        self.verify_timestamp(current_timestamp_sec)?;
+        self.verify_signature_by(&self.owner_peer_id, accept_v1)
+    }
+
+    /// Sign the v2 payload with the owner keypair
+    pub fn sign(&mut self, keypair: &KeyPair) -> Result<(), ClientError> {
+        self.signature = keypair
+            .sign(&self.signature_bytes())?
+            .get_raw_signature()
+            .bytes;
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::error::ClientError;
+use crate::key::Key;
+use crate::misc::extract_public_key;
+use crate::signing::{verify_v2_signature, CanonicalEncoder, DOMAIN_KEY_TRANSFER};
+use fluence_keypair::KeyPair;
+#[cfg(feature = "marine")]
+use marine_rs_sdk::marine;
+
+/// Handoff of the key ownership from `issued_by` to `new_owner_peer_id`.
+/// Key id stays the same, but the key, its tombstone and its permissions
+/// are controlled by the new owner after the transfer.
+#[cfg_attr(feature = "marine", marine)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KeyTransfer {
+    /// base58-encoded key id
+    pub key_id: String,
+    /// peer id of the current owner in base58
+    pub issued_by: String,
+    /// peer id of the new owner in base58
+    pub new_owner_peer_id: String,
+    /// timestamp in seconds
+    pub timestamp_issued: u64,
+    /// encoded and hashed previous fields signed by `issued_by`
+    pub signature: Vec<u8>,
+}
+
+impl KeyTransfer {
+    pub fn signature_bytes(&self) -> Vec<u8> {
+        CanonicalEncoder::new(DOMAIN_KEY_TRANSFER)
+            .string(&self.key_id)
+            .string(&self.issued_by)
+            .string(&self.new_owner_peer_id)
+            .u64(self.timestamp_issued)
+            .finish()
+    }
+
+    pub fn verify(&self, current_timestamp_sec: u64) -> Result<(), ClientError> {
+        if self.timestamp_issued > current_timestamp_sec {
+            return Err(ClientError::InvalidKeyTransferTimestamp);
+        }
+
+        let pk = extract_public_key(self.issued_by.clone())?;
+        verify_v2_signature(&pk, &self.signature, &self.signature_bytes()).map_err(|e| {
+            ClientError::InvalidKeyTransferSignature(self.key_id.clone(), self.issued_by.clone(), e)
+        })
+    }
+
+    /// Sign the v2 payload with the keypair of the current owner
+    pub fn sign(&mut self, keypair: &KeyPair) -> Result<(), ClientError> {
+        self.signature = keypair
+            .sign(&self.signature_bytes())?
+            .get_raw_signature()
+            .bytes;
+        Ok(())
+    }
+}
+
+/// Current owner of the key taking into account its transfer chain
+pub fn get_effective_owner(key: &Key, transfers: &[KeyTransfer]) -> String {
+    transfers
+        .last()
+        .map(|t| t.new_owner_peer_id.clone())
+        .unwrap_or_else(|| key.owner_peer_id.clone())
+}
+
+/// Owner of the key originally owned by `owner_peer_id` at `timestamp`, the only peer allowed
+/// to sign the key version or the key tombstone issued at that time: the new owner of the latest
+/// transfer issued not after `timestamp` or the original owner if there is no such transfer.
+/// `transfers` is the transfer chain ordered by `timestamp_issued`
+pub fn get_key_owner_at(owner_peer_id: &str, timestamp: u64, transfers: &[KeyTransfer]) -> String {
+    transfers
+        .iter()
+        .rev()
+        .find(|t| t.timestamp_issued <= timestamp)
+        .map(|t| t.new_owner_peer_id.clone())
+        .unwrap_or_else(|| owner_peer_id.to_string())
+}
+
+/// Verify the transfer chain of the key link by link starting from `owner_peer_id`:
+/// each transfer should be signed by the owner after the previous transfer and issued later than it
+pub fn verify_transfer_chain(
+    key_id: &str,
+    owner_peer_id: &str,
+    transfers: &[KeyTransfer],
+    current_timestamp_sec: u64,
+) -> Result<(), ClientError> {
+    let mut owner = owner_peer_id;
+    let mut previous_timestamp = None;
+    for transfer in transfers {
+        if transfer.key_id != key_id {
+            return Err(ClientError::InvalidKeyTransferKeyId(
+                transfer.key_id.clone(),
+                key_id.to_string(),
+            ));
+        }
+
+        if previous_timestamp.map_or(false, |t| transfer.timestamp_issued <= t) {
+            return Err(ClientError::NewerKeyTransferExists(key_id.to_string()));
+        }
+
+        if transfer.issued_by != owner {
+            return Err(ClientError::KeyTransferNotByOwner(
+                key_id.to_string(),
+                transfer.issued_by.clone(),
+                owner.to_string(),
+            ));
+        }
+
+        transfer.verify(current_timestamp_sec)?;
+        owner = &transfer.new_owner_peer_id;
+        previous_timestamp = Some(transfer.timestamp_issued);
+    }
+
+    Ok(())
+}
+
+/// Choose one of two verified transfer chains of the same key: the chain extending the other one,
+/// otherwise the one with the later transfer at the first differing link, so peers converge
+pub fn merge_transfer_chains(
+    stored: Vec<KeyTransfer>,
+    received: Vec<KeyTransfer>,
+) -> Vec<KeyTransfer> {
+    match stored.iter().zip(received.iter()).find(|(s, r)| s != r) {
+        Some((s, r)) if (r.timestamp_issued, &r.signature) > (s.timestamp_issued, &s.signature) => {
+            received
+        }
+        Some(_) => stored,
+        None if received.len() > stored.len() => received,
+        None => stored,
+    }
+}
+
+/// Verify the key signature by its owner at `timestamp_created`, return peer id of the signer
+pub fn verify_key_signature(
+    key: &Key,
+    transfers: &[KeyTransfer],
+    accept_v1: bool,
+) -> Result<String, ClientError> {
+    let signer = get_key_owner_at(&key.owner_peer_id, key.timestamp_created, transfers);
+    key.verify_signature_by(&signer, accept_v1)?;
+    Ok(signer)
+}
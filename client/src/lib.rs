@@ -0,0 +1,40 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+//! Registry keys, records and tombstones with their canonical signed payloads.
+//! Objects can be prepared, signed and verified natively without a running registry service
+
+#![allow(clippy::result_large_err)]
+
+pub mod defaults;
+pub mod error;
+pub mod expiry_policy;
+pub mod key;
+pub mod key_transfer;
+pub mod misc;
+pub mod record;
+pub mod signing;
+#[cfg(test)]
+mod tests;
+pub mod tombstone;
+
+pub use error::ClientError;
+pub use expiry_policy::ExpiryPolicy;
+pub use key::Key;
+pub use key_transfer::KeyTransfer;
+pub use misc::extract_public_key;
+pub use record::{Record, RecordMetadata};
+pub use tombstone::Tombstone;
@@ -0,0 +1,67 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::defaults::{CONFIG_FILE, CONFIG_FILE_ENV, DB_PATH, DB_PATH_ENV};
+use crate::error::ClientError;
+use fluence_keypair::PublicKey;
+use libp2p_identity::PeerId;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+fn parse_peer_id(peer_id: String) -> Result<PeerId, ClientError> {
+    PeerId::from_str(&peer_id).map_err(|e| ClientError::PeerIdParseError(format!("{:?}", e)))
+}
+
+pub fn extract_public_key(peer_id: String) -> Result<PublicKey, ClientError> {
+    PublicKey::try_from(
+        parse_peer_id(peer_id).map_err(|e| ClientError::PublicKeyExtractionError(e.to_string()))?,
+    )
+    .map_err(ClientError::PublicKeyDecodeError)
+}
+
+/// Value of the environment variable `name` or `default` if it's not set or empty
+pub fn get_env_or(name: &str, default: &str) -> String {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Database path from `REGISTRY_DB_PATH` or `DB_PATH` by default
+pub fn get_db_path() -> String {
+    get_env_or(DB_PATH_ENV, DB_PATH)
+}
+
+/// Config path from `REGISTRY_CONFIG_FILE` or `CONFIG_FILE` by default
+pub fn get_config_path() -> String {
+    get_env_or(CONFIG_FILE_ENV, CONFIG_FILE)
+}
+
+/// Optional values are stored as strings, empty for a missing value
+pub fn get_custom_option(value: String) -> Vec<String> {
+    if value.is_empty() {
+        vec![]
+    } else {
+        vec![value]
+    }
+}
+
+pub fn from_custom_option(value: Vec<String>) -> String {
+    if value.is_empty() {
+        "".to_string()
+    } else {
+        value[0].clone()
+    }
+}
@@ -0,0 +1,215 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::error::ClientError;
+use crate::misc::extract_public_key;
+use crate::signing::{verify_signature, CanonicalEncoder, DOMAIN_RECORD, DOMAIN_RECORD_METADATA};
+use fluence_keypair::KeyPair;
+#[cfg(feature = "marine")]
+use marine_rs_sdk::marine;
+use sha2::{Digest, Sha256};
+
+#[cfg_attr(feature = "marine", marine)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone)]
+pub struct RecordMetadata {
+    /// base58-encoded key id
+    pub key_id: String,
+    /// peer id of the issuer in base58
+    pub issued_by: String,
+    /// peer_id of hoster
+    pub peer_id: String,
+    /// timestamp in seconds
+    pub timestamp_issued: u64,
+    /// solution for the key challenge, used for permissions
+    pub solution: Vec<u8>,
+    /// any string
+    pub value: String,
+    /// optional (length is 0 or 1), base58 relay id
+    pub relay_id: Vec<String>,
+    /// optional (length is 0 or 1), advertising service id
+    pub service_id: Vec<String>,
+    /// optional (length is 0 or 1), record lifetime in seconds after `timestamp_created`
+    pub ttl: Vec<u64>,
+    /// encoded and hashed previous fields signed by `issued_by`
+    pub issuer_signature: Vec<u8>,
+}
+
+impl RecordMetadata {
+    pub fn signature_bytes(&self) -> Vec<u8> {
+        CanonicalEncoder::new(DOMAIN_RECORD_METADATA)
+            .string(&self.key_id)
+            .string(&self.issued_by)
+            .string(&self.peer_id)
+            .u64(self.timestamp_issued)
+            .bytes(&self.solution)
+            .string(&self.value)
+            .strings(&self.relay_id)
+            .strings(&self.service_id)
+            .u64s(&self.ttl)
+            .finish()
+    }
+
+    /// Legacy v1 payload, see `signing::verify_signature`
+    pub fn signature_bytes_v1(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.push(self.key_id.len() as u8);
+        bytes.extend(self.key_id.as_bytes());
+
+        bytes.push(self.issued_by.len() as u8);
+        bytes.extend(self.issued_by.as_bytes());
+
+        bytes.push(self.peer_id.len() as u8);
+        bytes.extend(self.peer_id.as_bytes());
+
+        bytes.extend(self.timestamp_issued.to_le_bytes());
+
+        bytes.push(self.solution.len() as u8);
+        bytes.extend(&self.solution);
+
+        bytes.push(self.value.len() as u8);
+        bytes.extend(self.value.as_bytes());
+
+        // list counts are u32 as usize on wasm32, where the service signs records
+        bytes.extend((self.relay_id.len() as u32).to_le_bytes());
+        for id in &self.relay_id {
+            bytes.push(id.len() as u8);
+            bytes.extend(id.as_bytes());
+        }
+
+        bytes.extend((self.service_id.len() as u32).to_le_bytes());
+        for id in &self.service_id {
+            bytes.push(id.len() as u8);
+            bytes.extend(id.as_bytes());
+        }
+
+        // ttl is appended only if it's set, so signatures of records without ttl stay valid
+        if !self.ttl.is_empty() {
+            bytes.extend((self.ttl.len() as u32).to_le_bytes());
+            for ttl in &self.ttl {
+                bytes.extend(ttl.to_le_bytes());
+            }
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(bytes);
+        hasher.finalize().to_vec()
+    }
+
+    pub fn verify(&self, current_timestamp_sec: u64, accept_v1: bool) -> Result<(), ClientError> {
+        if self.timestamp_issued > current_timestamp_sec {
+            return Err(ClientError::InvalidRecordMetadataTimestamp);
+        }
+
+        let pk = extract_public_key(self.issued_by.clone())?;
+        verify_signature(
+            &pk,
+            &self.issuer_signature,
+            &self.signature_bytes(),
+            || self.signature_bytes_v1(),
+            accept_v1,
+        )
+        .map_err(|e| {
+            ClientError::InvalidRecordMetadataSignature(
+                self.key_id.clone(),
+                self.issued_by.clone(),
+                e,
+            )
+        })
+    }
+
+    /// Sign the v2 payload with the issuer keypair
+    pub fn sign(&mut self, keypair: &KeyPair) -> Result<(), ClientError> {
+        self.issuer_signature = keypair
+            .sign(&self.signature_bytes())?
+            .get_raw_signature()
+            .bytes;
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "marine", marine)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone)]
+pub struct Record {
+    /// record metadata
+    pub metadata: RecordMetadata,
+    /// timestamp in seconds
+    pub timestamp_created: u64,
+    /// encoded and hashed previous fields signed by `metadata.peer_id`
+    pub signature: Vec<u8>,
+}
+
+impl Record {
+    pub fn signature_bytes(&self) -> Vec<u8> {
+        CanonicalEncoder::new(DOMAIN_RECORD)
+            .bytes(&self.metadata.signature_bytes())
+            .bytes(&self.metadata.issuer_signature)
+            .u64(self.timestamp_created)
+            .finish()
+    }
+
+    /// Legacy v1 payload, it's the hash of an empty buffer covering neither metadata nor timestamp
+    pub fn signature_bytes_v1(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut metadata = self.metadata.signature_bytes_v1();
+        metadata.push(self.metadata.issuer_signature.len() as u8);
+        metadata.extend(&self.metadata.issuer_signature);
+
+        bytes.push(metadata.len() as u8);
+        bytes.append(&mut metadata);
+
+        bytes.extend(self.timestamp_created.to_le_bytes());
+        let mut hasher = Sha256::new();
+        hasher.update(metadata);
+        hasher.finalize().to_vec()
+    }
+
+    /// Check timestamps and signatures of the record and its metadata,
+    /// signatures of v1 payloads are accepted if `accept_v1` is set
+    pub fn verify(&self, current_timestamp_sec: u64, accept_v1: bool) -> Result<(), ClientError> {
+        if self.timestamp_created > current_timestamp_sec {
+            return Err(ClientError::InvalidRecordTimestamp);
+        }
+
+        self.metadata.verify(current_timestamp_sec, accept_v1)?;
+
+        let pk = extract_public_key(self.metadata.peer_id.clone())?;
+        verify_signature(
+            &pk,
+            &self.signature,
+            &self.signature_bytes(),
+            || self.signature_bytes_v1(),
+            accept_v1,
+        )
+        .map_err(|e| {
+            ClientError::InvalidRecordSignature(
+                self.metadata.key_id.clone(),
+                self.metadata.peer_id.clone(),
+                e,
+            )
+        })
+    }
+
+    /// Sign the v2 payload with the host keypair, the metadata should be already signed
+    pub fn sign(&mut self, keypair: &KeyPair) -> Result<(), ClientError> {
+        self.signature = keypair
+            .sign(&self.signature_bytes())?
+            .get_raw_signature()
+            .bytes;
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use fluence_keypair::error::VerificationError;
+use fluence_keypair::{PublicKey, Signature};
+use sha2::{Digest, Sha256};
+
+/// Version of the signed payload format, the first byte of every v2 payload.
+/// v1 payloads have no version and prefix fields with `len() as u8`
+pub const SIGNATURE_FORMAT_V2: u8 = 2;
+
+/// Domain tags of signed object types, so a signature of one type can't be replayed as another
+pub const DOMAIN_KEY: &str = "fluence-registry/key/v2";
+pub const DOMAIN_RECORD_METADATA: &str = "fluence-registry/record_metadata/v2";
+pub const DOMAIN_RECORD: &str = "fluence-registry/record/v2";
+pub const DOMAIN_TOMBSTONE: &str = "fluence-registry/tombstone/v2";
+pub const DOMAIN_KEY_TOMBSTONE: &str = "fluence-registry/key_tombstone/v2";
+pub const DOMAIN_KEY_TRANSFER: &str = "fluence-registry/key_transfer/v2";
+pub const DOMAIN_DELEGATION: &str = "fluence-registry/delegation/v2";
+
+/// Canonical v2 encoding of signed payloads: the format version and the domain tag of the object type
+/// followed by the fields in order,
+/// integers are encoded as u64 little-endian, bytes and strings are prefixed with their u64 length,
+/// lists are prefixed with the number of elements. The result is sha256 of the payload
+pub struct CanonicalEncoder {
+    bytes: Vec<u8>,
+}
+
+impl CanonicalEncoder {
+    pub fn new(domain: &str) -> Self {
+        Self {
+            bytes: vec![SIGNATURE_FORMAT_V2],
+        }
+        .string(domain)
+    }
+
+    pub fn u64(mut self, value: u64) -> Self {
+        self.bytes.extend(value.to_le_bytes());
+        self
+    }
+
+    pub fn bytes(self, value: &[u8]) -> Self {
+        let mut encoder = self.u64(value.len() as u64);
+        encoder.bytes.extend(value);
+        encoder
+    }
+
+    pub fn string(self, value: &str) -> Self {
+        self.bytes(value.as_bytes())
+    }
+
+    pub fn strings(self, values: &[String]) -> Self {
+        values
+            .iter()
+            .fold(self.u64(values.len() as u64), |encoder, value| {
+                encoder.string(value)
+            })
+    }
+
+    pub fn u64s(self, values: &[u64]) -> Self {
+        values
+            .iter()
+            .fold(self.u64(values.len() as u64), |encoder, value| {
+                encoder.u64(*value)
+            })
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.bytes);
+        hasher.finalize().to_vec()
+    }
+}
+
+/// Verify `signature` of the v2 payload `bytes` only,
+/// for key transfers, delegations and key tombstones that never had v1 payloads
+pub fn verify_v2_signature(
+    pk: &PublicKey,
+    signature: &[u8],
+    bytes: &[u8],
+) -> Result<(), VerificationError> {
+    let signature = Signature::from_bytes(pk.get_key_format(), signature.to_vec());
+    pk.verify(bytes, &signature)
+}
+
+/// Verify `signature` of the v2 payload `bytes`, signatures of the legacy v1 payload
+/// are accepted too if `accept_v1` is set.
+///
+/// v1 payloads are sha256 of the fields without the format version and the domain tag,
+/// strings and bytes are prefixed with `len() as u8`, so lengths over 255 bytes are truncated,
+/// lists are prefixed with the number of elements as u32. They are built by `signature_bytes_v1`
+/// of keys, records and tombstones only to verify old signatures, new ones are always made for v2 payloads
+pub fn verify_signature<F>(
+    pk: &PublicKey,
+    signature: &[u8],
+    bytes: &[u8],
+    v1_bytes: F,
+    accept_v1: bool,
+) -> Result<(), VerificationError>
+where
+    F: FnOnce() -> Vec<u8>,
+{
+    let signature = Signature::from_bytes(pk.get_key_format(), signature.to_vec());
+    pk.verify(bytes, &signature).or_else(|error| {
+        if accept_v1 && pk.verify(&v1_bytes(), &signature).is_ok() {
+            Ok(())
+        } else {
+            Err(error)
+        }
+    })
+}
@@ -0,0 +1,277 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use fluence_keypair::KeyPair;
+
+use crate::key_transfer::{get_key_owner_at, merge_transfer_chains, verify_transfer_chain};
+use crate::{ClientError, Key, KeyTransfer, Record, RecordMetadata, Tombstone};
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .concat()
+}
+
+fn signed_record(issuer_kp: &KeyPair, host_kp: &KeyPair) -> Record {
+    let mut metadata = RecordMetadata {
+        key_id: Key::get_id("some_key", &issuer_kp.get_peer_id().to_base58()),
+        issued_by: issuer_kp.get_peer_id().to_base58(),
+        peer_id: host_kp.get_peer_id().to_base58(),
+        timestamp_issued: 100,
+        value: "value".to_string(),
+        relay_id: vec!["relay_id".to_string()],
+        ttl: vec![60],
+        ..Default::default()
+    };
+    metadata.sign(issuer_kp).unwrap();
+
+    let mut record = Record {
+        metadata,
+        timestamp_created: 100,
+        ..Default::default()
+    };
+    record.sign(host_kp).unwrap();
+    record
+}
+
+#[test]
+fn golden_vectors() {
+    let key = Key::new(
+        "a".repeat(300),
+        "owner".to_string(),
+        100,
+        vec![1, 2],
+        "open".to_string(),
+        vec![],
+    );
+    assert_eq!(
+        to_hex(&key.signature_bytes()),
+        "7efe1cbbbeede6ff0a12f28386c85d14c367987d2af85e9931f20f23094e49fc"
+    );
+
+    let metadata = RecordMetadata {
+        key_id: "key".to_string(),
+        issued_by: "issuer".to_string(),
+        peer_id: "peer".to_string(),
+        timestamp_issued: 100,
+        value: "v".repeat(300),
+        relay_id: vec!["relay".to_string()],
+        ttl: vec![60],
+        issuer_signature: vec![3; 4],
+        ..Default::default()
+    };
+    assert_eq!(
+        to_hex(&metadata.signature_bytes()),
+        "e68a9462b7a21ddb9cbb42142cbaef9153df8f5a6409f8a931889693c2d63a2f"
+    );
+
+    let record = Record {
+        metadata,
+        timestamp_created: 150,
+        ..Default::default()
+    };
+    assert_eq!(
+        to_hex(&record.signature_bytes()),
+        "7080ed7a7bed87999f3198fe1d5aec4dd2c64e98ecb85b3add06b9bf648d7143"
+    );
+
+    let tombstone = Tombstone {
+        key_id: "key".to_string(),
+        issued_by: "issuer".to_string(),
+        peer_id: "peer".to_string(),
+        timestamp_issued: 100,
+        ..Default::default()
+    };
+    assert_eq!(
+        to_hex(&tombstone.signature_bytes()),
+        "3800ccbfd1b11bf98c4a8c1011798135097b2912c0dbc307245c31942fe3c075"
+    );
+}
+
+/// v1 payloads signed by the service on wasm32, list counts are 4 bytes there
+#[test]
+fn v1_golden_vectors() {
+    let mut metadata = RecordMetadata {
+        key_id: "key".to_string(),
+        issued_by: "issuer".to_string(),
+        peer_id: "peer".to_string(),
+        timestamp_issued: 100,
+        value: "value".to_string(),
+        relay_id: vec!["relay".to_string()],
+        ..Default::default()
+    };
+    assert_eq!(
+        to_hex(&metadata.signature_bytes_v1()),
+        "def2a7c317cb2dd7973d92c3352c357976e4891bc18164404b27450ad2f5af38"
+    );
+
+    metadata.solution = vec![1, 2];
+    metadata.service_id = vec!["service".to_string()];
+    metadata.ttl = vec![60];
+    assert_eq!(
+        to_hex(&metadata.signature_bytes_v1()),
+        "05079337fee8b40e76c80f83656562ca5582572bb8418c26d8ed79982357e515"
+    );
+}
+
+#[test]
+fn signed_key_is_verified() {
+    let kp = KeyPair::generate_ed25519();
+    let mut key = Key::new(
+        "some_key".to_string(),
+        kp.get_peer_id().to_base58(),
+        100,
+        vec![],
+        "".to_string(),
+        vec![],
+    );
+    key.sign(&kp).unwrap();
+    key.verify(150, false).unwrap();
+
+    assert!(matches!(
+        key.verify(50, false),
+        Err(ClientError::InvalidKeyTimestamp)
+    ));
+
+    key.label = "other_key".to_string();
+    assert!(matches!(
+        key.verify(150, false),
+        Err(ClientError::InvalidKeySignature(..))
+    ));
+}
+
+#[test]
+fn signed_record_is_verified() {
+    let issuer_kp = KeyPair::generate_ed25519();
+    let host_kp = KeyPair::generate_ed25519();
+    let mut record = signed_record(&issuer_kp, &host_kp);
+    record.verify(150, false).unwrap();
+
+    record.timestamp_created = 120;
+    assert!(matches!(
+        record.verify(150, false),
+        Err(ClientError::InvalidRecordSignature(..))
+    ));
+
+    record.metadata.value = "other_value".to_string();
+    assert!(matches!(
+        record.verify(150, false),
+        Err(ClientError::InvalidRecordMetadataSignature(..))
+    ));
+}
+
+#[test]
+fn signed_tombstone_is_verified() {
+    let kp = KeyPair::generate_ed25519();
+    let mut tombstone = Tombstone {
+        key_id: Key::get_id("some_key", &kp.get_peer_id().to_base58()),
+        issued_by: kp.get_peer_id().to_base58(),
+        peer_id: kp.get_peer_id().to_base58(),
+        timestamp_issued: 100,
+        ..Default::default()
+    };
+    tombstone.sign(&kp).unwrap();
+    tombstone.verify(150, false).unwrap();
+
+    assert!(matches!(
+        tombstone.verify(50, false),
+        Err(ClientError::InvalidTombstoneTimestamp)
+    ));
+}
+
+#[test]
+fn v1_signatures_are_accepted_only_if_enabled() {
+    let kp = KeyPair::generate_ed25519();
+    let mut key = Key::new(
+        "some_key".to_string(),
+        kp.get_peer_id().to_base58(),
+        100,
+        vec![],
+        "".to_string(),
+        vec![],
+    );
+    key.signature = kp
+        .sign(&key.signature_bytes_v1())
+        .unwrap()
+        .to_vec()
+        .to_vec();
+
+    key.verify(150, true).unwrap();
+    assert!(matches!(
+        key.verify(150, false),
+        Err(ClientError::InvalidKeySignature(..))
+    ));
+
+    let mut record = signed_record(&kp, &kp);
+    record.metadata.issuer_signature = kp
+        .sign(&record.metadata.signature_bytes_v1())
+        .unwrap()
+        .to_vec()
+        .to_vec();
+    record.signature = kp
+        .sign(&record.signature_bytes_v1())
+        .unwrap()
+        .to_vec()
+        .to_vec();
+
+    record.verify(150, true).unwrap();
+    assert!(matches!(
+        record.verify(150, false),
+        Err(ClientError::InvalidRecordMetadataSignature(..))
+    ));
+}
+
+#[test]
+fn transfer_chain_is_verified_link_by_link() {
+    let kps: Vec<KeyPair> = (0..3).map(|_| KeyPair::generate_ed25519()).collect();
+    let peer_ids: Vec<String> = kps.iter().map(|kp| kp.get_peer_id().to_base58()).collect();
+    let key_id = Key::get_id("some_key", &peer_ids[0]);
+    let transfers: Vec<KeyTransfer> = [(0, 1, 120), (1, 2, 140)]
+        .into_iter()
+        .map(|(from, to, timestamp_issued)| {
+            let mut transfer = KeyTransfer {
+                key_id: key_id.clone(),
+                issued_by: peer_ids[from].clone(),
+                new_owner_peer_id: peer_ids[to].clone(),
+                timestamp_issued,
+                ..Default::default()
+            };
+            transfer.sign(&kps[from]).unwrap();
+            transfer
+        })
+        .collect();
+
+    verify_transfer_chain(&key_id, &peer_ids[0], &transfers, 150).unwrap();
+    assert_eq!(get_key_owner_at(&peer_ids[0], 110, &transfers), peer_ids[0]);
+    assert_eq!(get_key_owner_at(&peer_ids[0], 130, &transfers), peer_ids[1]);
+    assert_eq!(get_key_owner_at(&peer_ids[0], 140, &transfers), peer_ids[2]);
+
+    assert!(matches!(
+        verify_transfer_chain(&key_id, &peer_ids[0], &transfers[1..], 150),
+        Err(ClientError::KeyTransferNotByOwner(..))
+    ));
+    assert!(matches!(
+        verify_transfer_chain(&key_id, &peer_ids[0], &transfers, 130),
+        Err(ClientError::InvalidKeyTransferTimestamp)
+    ));
+
+    // the longer chain extends the shorter one
+    let merged = merge_transfer_chains(transfers[..1].to_vec(), transfers.clone());
+    assert_eq!(merged, transfers);
+    let merged = merge_transfer_chains(transfers.clone(), transfers[..1].to_vec());
+    assert_eq!(merged, transfers);
+}
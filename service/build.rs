@@ -16,20 +16,33 @@
 use marine_rs_sdk_test::generate_marine_test_env;
 use marine_rs_sdk_test::ServiceDescription;
 fn main() {
-    let services = vec![(
-        "registry".to_string(),
-        ServiceDescription {
-            config_path: "Config.toml".to_string(),
-            modules_dir: None,
-        },
-    )];
+    let services = vec![
+        (
+            "registry".to_string(),
+            ServiceDescription {
+                config_path: "Config.toml".to_string(),
+                modules_dir: None,
+            },
+        ),
+        // the same service with the database and config paths overridden by module envs
+        (
+            "registry_env_paths".to_string(),
+            ServiceDescription {
+                config_path: "EnvPathsConfig.toml".to_string(),
+                modules_dir: None,
+            },
+        ),
+    ];
 
     let target = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap();
     if target != "wasm32" {
         generate_marine_test_env(services, "marine_test_env.rs", file!());
     }
 
+    println!("cargo:rerun-if-changed=src/change_api.rs");
+    println!("cargo:rerun-if-changed=src/digest_api.rs");
     println!("cargo:rerun-if-changed=src/key_api.rs");
+    println!("cargo:rerun-if-changed=src/key_tombstone_api.rs");
     println!("cargo:rerun-if-changed=src/record_api.rs");
     println!("cargo:rerun-if-changed=src/tombstone_api.rs");
     println!("cargo:rerun-if-changed=src/main.rs");
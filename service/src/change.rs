@@ -0,0 +1,32 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use marine_rs_sdk::marine;
+
+/// Entry of the local change log
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct Change {
+    /// monotonically increasing sequence number
+    pub seq: u64,
+    /// base58-encoded key id
+    pub key_id: String,
+    /// key_registered, key_removed, record_upserted, record_evicted, tombstone_added or expired
+    pub kind: String,
+    /// issuer of the changed record or tombstone, empty for key changes
+    pub issued_by: String,
+    /// peer id of the changed record or tombstone, empty for key changes
+    pub peer_id: String,
+}
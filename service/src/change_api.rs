@@ -0,0 +1,27 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::results::GetChangesResult;
+use crate::storage_impl::get_storage;
+use crate::wrapped_try;
+use marine_rs_sdk::marine;
+
+/// Local changes after `after_seq` in the order they happened. Clients should continue from
+/// the last received `seq`; if the first returned `seq` is greater than `after_seq + 1`,
+/// older changes were trimmed and the client should resync
+#[marine]
+pub fn get_changes(after_seq: u64, limit: u64) -> GetChangesResult {
+    wrapped_try(|| get_storage()?.get_changes(after_seq, limit)).into()
+}
@@ -0,0 +1,132 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::change::Change;
+use crate::defaults::{
+    CHANGES_LIMIT, CHANGES_PAGE_SIZE_LIMIT, CHANGES_TABLE_NAME, RECORDS_TABLE_NAME,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::InternalError;
+use crate::storage_impl::Storage;
+use marine_sqlite_connector::{State, Statement, Value};
+
+impl Storage {
+    pub fn create_changes_table(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE TABLE IF NOT EXISTS {CHANGES_TABLE_NAME} (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                key_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                issued_by TEXT NOT NULL,
+                peer_id TEXT NOT NULL
+            );
+        "))?;
+        Ok(())
+    }
+
+    pub fn log_change(
+        &self,
+        key_id: &str,
+        kind: &str,
+        issued_by: &str,
+        peer_id: &str,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT INTO {CHANGES_TABLE_NAME} (key_id, kind, issued_by, peer_id) VALUES (?, ?, ?, ?)"
+        ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.bind(2, &Value::String(kind.to_string()))?;
+        statement.bind(3, &Value::String(issued_by.to_string()))?;
+        statement.bind(4, &Value::String(peer_id.to_string()))?;
+        statement.next().map(drop)?;
+
+        Ok(())
+    }
+
+    /// Log a change for each row of records table matching `condition`, should be called before
+    /// the rows are changed with the same condition
+    pub fn log_records_changes(&self, condition: &str, kind: &str) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "INSERT INTO {CHANGES_TABLE_NAME} (key_id, kind, issued_by, peer_id) \
+             SELECT key_id, ?, issued_by, peer_id FROM {RECORDS_TABLE_NAME} WHERE {condition}"
+        ))?;
+        statement.bind(1, &Value::String(kind.to_string()))?;
+        statement.next().map(drop)?;
+
+        Ok(())
+    }
+
+    /// Sequence number of the latest change, 0 if there are no changes
+    pub fn get_last_change_seq(&self) -> Result<u64, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT COALESCE(MAX(seq), 0) FROM {CHANGES_TABLE_NAME}"))?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<i64>(0)? as u64)
+        } else {
+            Err(InternalError(
+                "MAX should always return something".to_string(),
+            ))
+        }
+    }
+
+    /// Changes with sequence numbers greater than `after_seq` and the latest sequence number.
+    /// `limit` is capped by `CHANGES_PAGE_SIZE_LIMIT`, 0 means the maximum page size
+    pub fn get_changes(
+        &self,
+        after_seq: u64,
+        limit: u64,
+    ) -> Result<(Vec<Change>, u64), ServiceError> {
+        let limit = if limit == 0 || limit > CHANGES_PAGE_SIZE_LIMIT {
+            CHANGES_PAGE_SIZE_LIMIT
+        } else {
+            limit
+        };
+
+        let mut statement = self.connection.prepare(f!(
+            "SELECT seq, key_id, kind, issued_by, peer_id FROM {CHANGES_TABLE_NAME} \
+             WHERE seq > ? ORDER BY seq LIMIT ?"
+        ))?;
+        statement.bind(1, &Value::Integer(after_seq as i64))?;
+        statement.bind(2, &Value::Integer(limit as i64))?;
+
+        let mut changes = vec![];
+        while let State::Row = statement.next()? {
+            changes.push(read_change(&statement)?);
+        }
+
+        Ok((changes, self.get_last_change_seq()?))
+    }
+
+    /// Keep only the latest `CHANGES_LIMIT` changes
+    pub fn trim_changes(&self) -> Result<u64, ServiceError> {
+        let last_seq = self.get_last_change_seq()?;
+        self.connection.execute(f!(
+            "DELETE FROM {CHANGES_TABLE_NAME} WHERE seq <= {last_seq} - {CHANGES_LIMIT}"
+        ))?;
+        Ok(self.connection.changes() as u64)
+    }
+}
+
+pub fn read_change(statement: &Statement) -> Result<Change, ServiceError> {
+    Ok(Change {
+        seq: statement.read::<i64>(0)? as u64,
+        key_id: statement.read::<String>(1)?,
+        kind: statement.read::<String>(2)?,
+        issued_by: statement.read::<String>(3)?,
+        peer_id: statement.read::<String>(4)?,
+    })
+}
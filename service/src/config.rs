@@ -14,32 +14,208 @@
  * limitations under the License.
  */
 
+use marine_rs_sdk::CallParameters;
 use serde::{Deserialize, Serialize};
 use std::fs;
 
-use crate::defaults::{CONFIG_FILE, DEFAULT_EXPIRED_AGE, DEFAULT_STALE_AGE};
+use crate::defaults::{
+    DEFAULT_EXPIRED_AGE, DEFAULT_KEY_WRITES_QUOTA, DEFAULT_MAX_KEY_RECORDS_LIMIT,
+    DEFAULT_MAX_RECORD_TTL, DEFAULT_QUOTA_WINDOW, DEFAULT_RECORD_WRITES_QUOTA, DEFAULT_STALE_AGE,
+    DEFAULT_TOMBSTONE_RETENTION, DEFAULT_TRUSTED_QUOTA_MULTIPLIER, DEFAULT_TRUSTED_WEIGHT,
+    EVICTION_POLICY_ISSUER_DIVERSITY, EVICTION_POLICY_RECENCY, EVICTION_POLICY_WEIGHT,
+    QUOTA_KIND_KEY_WRITES, RECORDS_LIMIT,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    ConfigFileError, InvalidConfig, NotAdmin, NotHost, UnknownEvictionPolicy,
+};
+use crate::expiry_policy::ExpiryPolicy;
+use crate::storage_impl::get_storage;
+use registry_client::misc::get_config_path;
 
-#[derive(Deserialize, Serialize)]
+/// Missing fields are filled with defaults
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     pub expired_timeout: u64,
     pub stale_timeout: u64,
+    /// upper bound for ttl set by record issuers
+    pub max_record_ttl: u64,
+    /// how long tombstones are kept after `timestamp_issued`
+    pub tombstone_retention: u64,
+    /// non-host records limit for keys without their own limit
+    pub records_limit: u64,
+    /// upper bound for limits set by key owners
+    pub max_key_records_limit: u64,
+    /// which record is evicted when the limit is reached: weight, recency or issuer_diversity
+    pub eviction_policy: String,
+    /// length of the window in seconds in which writes of each initiator are counted
+    pub quota_window: u64,
+    /// keys and key tombstones an initiator can write per window
+    pub key_writes_quota: u64,
+    /// records and tombstones an initiator can write per window
+    pub record_writes_quota: u64,
+    /// initiators with at least this trust-graph weight get relaxed quotas
+    pub trusted_weight: u32,
+    pub trusted_quota_multiplier: u64,
+    /// accept signatures of the legacy v1 payloads besides v2, domain separation of v2 payloads
+    /// isn't enforced while enabled. Disabled in fresh configs, enabled in existing configs
+    /// written before the option was added
+    #[serde(default = "accept_v1_signatures_of_existing_config")]
+    pub accept_v1_signatures: bool,
+    /// peer ids allowed to change the config besides the host
+    pub admins: Vec<String>,
 }
 
-pub fn write_config(config: Config) {
-    fs::write(CONFIG_FILE, toml::to_string(&config).unwrap()).unwrap();
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            expired_timeout: DEFAULT_EXPIRED_AGE,
+            stale_timeout: DEFAULT_STALE_AGE,
+            max_record_ttl: DEFAULT_MAX_RECORD_TTL,
+            tombstone_retention: DEFAULT_TOMBSTONE_RETENTION,
+            records_limit: RECORDS_LIMIT as u64,
+            max_key_records_limit: DEFAULT_MAX_KEY_RECORDS_LIMIT,
+            eviction_policy: EVICTION_POLICY_WEIGHT.to_string(),
+            quota_window: DEFAULT_QUOTA_WINDOW,
+            key_writes_quota: DEFAULT_KEY_WRITES_QUOTA,
+            record_writes_quota: DEFAULT_RECORD_WRITES_QUOTA,
+            trusted_weight: DEFAULT_TRUSTED_WEIGHT,
+            trusted_quota_multiplier: DEFAULT_TRUSTED_QUOTA_MULTIPLIER,
+            accept_v1_signatures: false,
+            admins: vec![],
+        }
+    }
 }
 
-pub fn load_config() -> Config {
-    let file_content = fs::read_to_string(CONFIG_FILE).unwrap();
-    let config: Config = toml::from_str(&file_content).unwrap();
-    config
+/// Existing configs without the field were used with v1 signatures
+fn accept_v1_signatures_of_existing_config() -> bool {
+    true
 }
 
-pub fn create_config() {
-    if fs::metadata(CONFIG_FILE).is_err() {
-        write_config(Config {
-            expired_timeout: DEFAULT_EXPIRED_AGE,
-            stale_timeout: DEFAULT_STALE_AGE,
-        });
+impl Config {
+    pub fn validate(&self) -> Result<(), ServiceError> {
+        let non_zero = [
+            ("expired_timeout", self.expired_timeout),
+            ("stale_timeout", self.stale_timeout),
+            ("max_record_ttl", self.max_record_ttl),
+            ("tombstone_retention", self.tombstone_retention),
+            ("records_limit", self.records_limit),
+            ("max_key_records_limit", self.max_key_records_limit),
+            ("quota_window", self.quota_window),
+            ("key_writes_quota", self.key_writes_quota),
+            ("record_writes_quota", self.record_writes_quota),
+            ("trusted_weight", self.trusted_weight as u64),
+            ("trusted_quota_multiplier", self.trusted_quota_multiplier),
+        ];
+        if let Some((name, _)) = non_zero.iter().find(|(_, value)| *value == 0) {
+            return Err(InvalidConfig(f!("{name} should be greater than zero")));
+        }
+
+        // keys should be republished before they expire
+        if self.stale_timeout >= self.expired_timeout {
+            let (stale_timeout, expired_timeout) = (self.stale_timeout, self.expired_timeout);
+            return Err(InvalidConfig(f!(
+                "stale_timeout {stale_timeout} should be less than expired_timeout {expired_timeout}"
+            )));
+        }
+
+        match self.eviction_policy.as_str() {
+            EVICTION_POLICY_WEIGHT | EVICTION_POLICY_RECENCY | EVICTION_POLICY_ISSUER_DIVERSITY => {
+                Ok(())
+            }
+            other => Err(UnknownEvictionPolicy(other.to_string())),
+        }
+    }
+
+    /// Limit set by the key owner capped by `max_key_records_limit` or the global one
+    pub fn get_records_limit(&self, key_records_limit: Option<u64>) -> u64 {
+        key_records_limit.map_or(self.records_limit, |limit| {
+            limit.min(self.max_key_records_limit)
+        })
+    }
+
+    /// Writes of `kind` allowed per window for an initiator with trust-graph `weight`
+    pub fn get_write_quota(&self, kind: &str, weight: u32) -> u64 {
+        let quota = match kind {
+            QUOTA_KIND_KEY_WRITES => self.key_writes_quota,
+            _ => self.record_writes_quota,
+        };
+
+        if weight >= self.trusted_weight {
+            quota.saturating_mul(self.trusted_quota_multiplier)
+        } else {
+            quota
+        }
+    }
+
+    /// Start of the quota window containing `current_timestamp_sec`
+    pub fn get_quota_window_start(&self, current_timestamp_sec: u64) -> u64 {
+        current_timestamp_sec - current_timestamp_sec % self.quota_window
+    }
+}
+
+/// Validate and write the config
+pub fn write_config(config: &Config) -> Result<(), ServiceError> {
+    config.validate()?;
+    let path = get_config_path();
+    let content =
+        toml::to_string(config).map_err(|e| ConfigFileError(path.clone(), e.to_string()))?;
+    fs::write(&path, content).map_err(|e| ConfigFileError(path, e.to_string()))
+}
+
+/// Read the config without validation, missing fields are filled with defaults
+fn read_config() -> Result<Config, ServiceError> {
+    let path = get_config_path();
+    let content =
+        fs::read_to_string(&path).map_err(|e| ConfigFileError(path.clone(), e.to_string()))?;
+    toml::from_str(&content).map_err(|e| ConfigFileError(path, e.to_string()))
+}
+
+/// Read the effective config, missing fields are filled with defaults
+pub fn load_config() -> Result<Config, ServiceError> {
+    let config = read_config()?;
+    config.validate()?;
+    Ok(config)
+}
+
+pub fn create_config() -> Result<(), ServiceError> {
+    if fs::metadata(get_config_path()).is_err() {
+        write_config(&Config::default())?;
+    }
+
+    Ok(())
+}
+
+/// Check the particle is initiated by the host or one of the configured admins
+pub fn check_admin(config: &Config, call_parameters: &CallParameters) -> Result<(), ServiceError> {
+    let init_peer_id = &call_parameters.init_peer_id;
+    (*init_peer_id == call_parameters.host_id || config.admins.contains(init_peer_id))
+        .then_some(())
+        .ok_or_else(|| NotAdmin(init_peer_id.clone()))
+}
+
+pub fn check_host(call_parameters: &CallParameters) -> Result<(), ServiceError> {
+    (call_parameters.init_peer_id == call_parameters.host_id)
+        .then_some(())
+        .ok_or_else(|| NotHost(call_parameters.init_peer_id.clone()))
+}
+
+/// All config mutators should go through this function to be restricted to admins,
+/// only the updated config is validated before it's written, so an invalid one can be fixed
+pub fn update_config<F>(call_parameters: &CallParameters, update: F) -> Result<(), ServiceError>
+where
+    F: FnOnce(&mut Config),
+{
+    let mut config = read_config()?;
+    check_admin(&config, call_parameters)?;
+    let expiry_policy = ExpiryPolicy::from(&config);
+    update(&mut config);
+    write_config(&config)?;
+
+    // cached key digests depend on expiration of records and tombstones
+    if ExpiryPolicy::from(&config) != expiry_policy {
+        get_storage()?.clear_key_digests()?;
     }
+    Ok(())
 }
@@ -16,11 +16,49 @@
 
 pub static KEYS_TABLE_NAME: &str = "keys_table";
 pub static RECORDS_TABLE_NAME: &str = "records_table";
-pub static CONFIG_FILE: &str = "/tmp/Config.toml";
-pub static DB_PATH: &str = "/tmp/registry.db";
-pub static DEFAULT_STALE_AGE: u64 = 60 * 60;
-pub static DEFAULT_EXPIRED_AGE: u64 = 24 * 60 * 60;
+pub static KEY_TOMBSTONES_TABLE_NAME: &str = "key_tombstones_table";
+pub static SCHEMA_VERSION_TABLE_NAME: &str = "schema_version_table";
+pub static CHANGES_TABLE_NAME: &str = "changes_table";
+pub static QUOTAS_TABLE_NAME: &str = "quotas_table";
+pub static KEY_TRANSFERS_TABLE_NAME: &str = "key_transfers_table";
+pub use registry_client::defaults::{
+    CONFIG_FILE, CONFIG_FILE_ENV, DB_PATH, DB_PATH_ENV, DEFAULT_EXPIRED_AGE,
+    DEFAULT_MAX_RECORD_TTL, DEFAULT_STALE_AGE, DEFAULT_TOMBSTONE_RETENTION,
+};
 pub static RECORDS_LIMIT: usize = 32;
+pub static DEFAULT_MAX_KEY_RECORDS_LIMIT: u64 = 1024;
+/// writes allowed per initiator in a quota window, multiplied for trusted initiators
+pub static DEFAULT_QUOTA_WINDOW: u64 = 60;
+pub static DEFAULT_KEY_WRITES_QUOTA: u64 = 32;
+pub static DEFAULT_RECORD_WRITES_QUOTA: u64 = 128;
+pub static DEFAULT_TRUSTED_WEIGHT: u32 = 1;
+pub static DEFAULT_TRUSTED_QUOTA_MULTIPLIER: u64 = 10;
+pub static KEYS_PAGE_SIZE_LIMIT: u64 = 100;
+pub static CHANGES_PAGE_SIZE_LIMIT: u64 = 1000;
+pub static CHANGES_LIMIT: u64 = 100_000;
+
+pub const CHALLENGE_TYPE_OPEN: &str = "open";
+pub const CHALLENGE_TYPE_OWNER: &str = "owner";
+pub const CHALLENGE_TYPE_ALLOWLIST: &str = "allowlist";
+pub const CHALLENGE_TYPE_DELEGATION: &str = "delegation";
+
+pub const LABEL_MATCH_EXACT: &str = "exact";
+pub const LABEL_MATCH_PREFIX: &str = "prefix";
+pub const LABEL_MATCH_SUBSTRING: &str = "substring";
+
+pub const EVICTION_POLICY_WEIGHT: &str = "weight";
+pub const EVICTION_POLICY_RECENCY: &str = "recency";
+pub const EVICTION_POLICY_ISSUER_DIVERSITY: &str = "issuer_diversity";
+
+pub const QUOTA_KIND_KEY_WRITES: &str = "key_writes";
+pub const QUOTA_KIND_RECORD_WRITES: &str = "record_writes";
+
+pub static CHANGE_KEY_REGISTERED: &str = "key_registered";
+pub static CHANGE_KEY_REMOVED: &str = "key_removed";
+pub static CHANGE_RECORD_UPSERTED: &str = "record_upserted";
+pub static CHANGE_RECORD_EVICTED: &str = "record_evicted";
+pub static CHANGE_TOMBSTONE_ADDED: &str = "tombstone_added";
+pub static CHANGE_EXPIRED: &str = "expired";
 
 pub static TRUSTED_TIMESTAMP_SERVICE_ID: &str = "peer";
 pub static TRUSTED_TIMESTAMP_FUNCTION_NAME: &str = "timestamp_sec";
@@ -0,0 +1,158 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::key::Key;
+use crate::key_transfer::KeyTransfer;
+use crate::record::Record;
+use crate::tombstone::Tombstone;
+use marine_rs_sdk::marine;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Digest of the key with its records and tombstones
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct KeyDigest {
+    pub key_id: String,
+    pub digest: Vec<u8>,
+}
+
+/// Merkle summary of keys with ids starting with `prefix`
+#[marine]
+#[derive(Debug, Default, Clone)]
+pub struct MerkleNode {
+    pub prefix: String,
+    pub digest: Vec<u8>,
+    /// number of keys under the node
+    pub count: u64,
+}
+
+fn push_bytes(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend((value.len() as u64).to_le_bytes());
+    bytes.extend(value);
+}
+
+/// Hash of the data replicated for the key. Only signed fields are included,
+/// local ones like weights and publishing timestamps are not,
+/// so peers with the same data get the same digest.
+pub fn get_key_digest(
+    key: &Key,
+    transfers: &[KeyTransfer],
+    records: &[Record],
+    tombstones: &[Tombstone],
+) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    push_bytes(&mut bytes, key.id.as_bytes());
+    bytes.extend(key.timestamp_created.to_le_bytes());
+    push_bytes(&mut bytes, &key.signature);
+
+    bytes.extend((transfers.len() as u64).to_le_bytes());
+    for transfer in transfers {
+        bytes.extend(transfer.timestamp_issued.to_le_bytes());
+        push_bytes(&mut bytes, &transfer.signature);
+    }
+
+    let mut records: Vec<&Record> = records.iter().collect();
+    records.sort_by(|l, r| {
+        (&l.metadata.peer_id, &l.metadata.issued_by)
+            .cmp(&(&r.metadata.peer_id, &r.metadata.issued_by))
+    });
+    bytes.extend((records.len() as u64).to_le_bytes());
+    for record in records {
+        push_bytes(&mut bytes, record.metadata.peer_id.as_bytes());
+        push_bytes(&mut bytes, record.metadata.issued_by.as_bytes());
+        bytes.extend(record.metadata.timestamp_issued.to_le_bytes());
+        push_bytes(&mut bytes, &record.metadata.signature_bytes());
+        push_bytes(&mut bytes, &record.metadata.issuer_signature);
+        bytes.extend(record.timestamp_created.to_le_bytes());
+        push_bytes(&mut bytes, &record.signature);
+    }
+
+    let mut tombstones: Vec<&Tombstone> = tombstones.iter().collect();
+    tombstones.sort_by(|l, r| (&l.peer_id, &l.issued_by).cmp(&(&r.peer_id, &r.issued_by)));
+    bytes.extend((tombstones.len() as u64).to_le_bytes());
+    for tombstone in tombstones {
+        push_bytes(&mut bytes, tombstone.peer_id.as_bytes());
+        push_bytes(&mut bytes, tombstone.issued_by.as_bytes());
+        bytes.extend(tombstone.timestamp_issued.to_le_bytes());
+        push_bytes(&mut bytes, &tombstone.issuer_signature);
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(bytes);
+    hasher.finalize().to_vec()
+}
+
+/// Merkle digest of key digests sorted by key id. Keys are grouped into a trie by key id chars,
+/// a node with a single key is a leaf, so the digest of a prefix is the same on every peer
+/// with the same keys under the prefix, regardless of other keys.
+pub fn get_merkle_digest(digests: &[KeyDigest], depth: usize) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+
+    if let [single] = digests {
+        hasher.update([0u8]);
+        hasher.update((single.key_id.len() as u64).to_le_bytes());
+        hasher.update(single.key_id.as_bytes());
+        hasher.update(&single.digest);
+        return hasher.finalize().to_vec();
+    }
+
+    hasher.update([1u8]);
+    for group in group_by_char(digests, depth) {
+        let c = group[0].key_id.as_bytes().get(depth).copied().unwrap_or(0);
+        hasher.update([c]);
+        hasher.update(get_merkle_digest(group, depth + 1));
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Split digests sorted by key id into groups with the same byte at `depth`,
+/// ids shorter than `depth` form their own group
+pub fn group_by_char(digests: &[KeyDigest], depth: usize) -> Vec<&[KeyDigest]> {
+    let mut groups = vec![];
+    let mut start = 0;
+    for i in 1..=digests.len() {
+        if i == digests.len()
+            || digests[i].key_id.as_bytes().get(depth)
+                != digests[start].key_id.as_bytes().get(depth)
+        {
+            groups.push(&digests[start..i]);
+            start = i;
+        }
+    }
+
+    groups
+}
+
+/// Ids of `local` items which are missing in `remote` or have another digest there
+fn diff_by_id<T>(local: &[T], remote: &[T], entry: fn(&T) -> (&str, &[u8])) -> Vec<String> {
+    let remote: HashMap<&str, &[u8]> = remote.iter().map(entry).collect();
+    local
+        .iter()
+        .map(entry)
+        .filter(|(id, digest)| remote.get(id) != Some(digest))
+        .map(|(id, _)| id.to_string())
+        .collect()
+}
+
+/// Prefixes of `local` nodes which are missing in `remote` or differ from them
+pub fn diff_merkle_nodes(local: &[MerkleNode], remote: &[MerkleNode]) -> Vec<String> {
+    diff_by_id(local, remote, |n| (n.prefix.as_str(), n.digest.as_slice()))
+}
+
+/// Ids of `local` keys which are missing in `remote` or differ from them
+pub fn diff_key_digests(local: &[KeyDigest], remote: &[KeyDigest]) -> Vec<String> {
+    diff_by_id(local, remote, |d| (d.key_id.as_str(), d.digest.as_slice()))
+}
@@ -0,0 +1,77 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::defaults::KEYS_PAGE_SIZE_LIMIT;
+use crate::digest::{KeyDigest, MerkleNode};
+use crate::error::ServiceError;
+use crate::results::{KeyDigestResult, KeyDigestsResult, MerkleNodeResult};
+use crate::storage_impl::get_storage;
+use crate::tetraplets_checkers::check_timestamp_tetraplets;
+use crate::wrapped_try;
+use marine_rs_sdk::marine;
+
+/// Digest of the key with its records and tombstones, equal on peers holding the same data
+#[marine]
+pub fn get_key_digest(key_id: String, current_timestamp_sec: u64) -> KeyDigestResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 1)?;
+        get_storage()?.get_key_digest(key_id, current_timestamp_sec)
+    })
+    .into()
+}
+
+/// Merkle node for keys with ids starting with `prefix` and its children for one more char.
+/// Peers compare nodes starting from the root (empty prefix) and descend only into differing children
+#[marine]
+pub fn get_merkle_node(prefix: String, current_timestamp_sec: u64) -> MerkleNodeResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 1)?;
+        get_storage()?.get_merkle_node(prefix, current_timestamp_sec)
+    })
+    .into()
+}
+
+/// Digests of all keys with ids starting with `prefix`, used at the bottom of the Merkle tree
+/// to find differing keys, fails if there are more than `KEYS_PAGE_SIZE_LIMIT` keys
+#[marine]
+pub fn get_key_digests(prefix: String, current_timestamp_sec: u64) -> KeyDigestsResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 1)?;
+        let storage = get_storage()?;
+        let count = storage.get_key_ids_by_prefix(&prefix)?.len();
+        if count as u64 > KEYS_PAGE_SIZE_LIMIT {
+            return Err(ServiceError::TooManyKeys(count, KEYS_PAGE_SIZE_LIMIT));
+        }
+
+        storage.get_key_digests(&prefix, current_timestamp_sec)
+    })
+    .into()
+}
+
+/// Prefixes of `local` nodes which are missing in `remote` or have another digest,
+/// used to descend only into differing children during replication
+#[marine]
+pub fn diff_merkle_nodes(local: Vec<MerkleNode>, remote: Vec<MerkleNode>) -> Vec<String> {
+    crate::digest::diff_merkle_nodes(&local, &remote)
+}
+
+/// Ids of `local` keys which are missing in `remote` or have another digest
+#[marine]
+pub fn diff_key_digests(local: Vec<KeyDigest>, remote: Vec<KeyDigest>) -> Vec<String> {
+    crate::digest::diff_key_digests(&local, &remote)
+}
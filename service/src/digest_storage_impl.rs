@@ -0,0 +1,193 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::defaults::{KEYS_TABLE_NAME, RECORDS_TABLE_NAME};
+use crate::digest::{get_key_digest, get_merkle_digest, group_by_char, KeyDigest, MerkleNode};
+use crate::error::ServiceError;
+use crate::expiry_policy::load_expiry_policy;
+use crate::storage_impl::Storage;
+use marine_sqlite_connector::{State, Value};
+
+impl Storage {
+    /// Cached key digests are reset by triggers on every change of the replicated data,
+    /// expiration of records and tombstones is handled by `digest_valid_until`
+    pub fn add_key_digest_columns(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            ALTER TABLE {KEYS_TABLE_NAME} ADD COLUMN digest BLOB;
+            ALTER TABLE {KEYS_TABLE_NAME} ADD COLUMN digest_valid_until INTEGER;
+
+            CREATE TRIGGER IF NOT EXISTS records_insert_digest_trigger
+                AFTER INSERT ON {RECORDS_TABLE_NAME}
+            BEGIN
+                UPDATE {KEYS_TABLE_NAME} SET digest = NULL WHERE key_id = NEW.key_id;
+            END;
+            CREATE TRIGGER IF NOT EXISTS records_update_digest_trigger
+                AFTER UPDATE ON {RECORDS_TABLE_NAME}
+            BEGIN
+                UPDATE {KEYS_TABLE_NAME} SET digest = NULL WHERE key_id IN (OLD.key_id, NEW.key_id);
+            END;
+            CREATE TRIGGER IF NOT EXISTS records_delete_digest_trigger
+                AFTER DELETE ON {RECORDS_TABLE_NAME}
+            BEGIN
+                UPDATE {KEYS_TABLE_NAME} SET digest = NULL WHERE key_id = OLD.key_id;
+            END;
+            CREATE TRIGGER IF NOT EXISTS keys_update_digest_trigger
+                AFTER UPDATE OF timestamp_created, signature, transfer_timestamp_issued, transfer_signature
+                ON {KEYS_TABLE_NAME}
+                WHEN OLD.timestamp_created IS NOT NEW.timestamp_created
+                    OR OLD.signature IS NOT NEW.signature
+                    OR OLD.transfer_timestamp_issued IS NOT NEW.transfer_timestamp_issued
+                    OR OLD.transfer_signature IS NOT NEW.transfer_signature
+            BEGIN
+                UPDATE {KEYS_TABLE_NAME} SET digest = NULL WHERE key_id = NEW.key_id;
+            END;
+        "))?;
+        Ok(())
+    }
+
+    /// Reset all cached digests, e.g. after the expiry policy is changed
+    pub fn clear_key_digests(&self) -> Result<(), ServiceError> {
+        self.connection
+            .execute(f!("UPDATE {KEYS_TABLE_NAME} SET digest = NULL"))?;
+        Ok(())
+    }
+
+    /// Cached digest of the key if it is still valid at `current_timestamp_sec`
+    fn get_cached_key_digest(
+        &self,
+        key_id: &str,
+        current_timestamp_sec: u64,
+    ) -> Result<Option<Vec<u8>>, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT digest FROM {KEYS_TABLE_NAME} \
+             WHERE key_id = ? AND digest IS NOT NULL AND digest_valid_until > ?"))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.bind(2, &Value::Integer(current_timestamp_sec as i64))?;
+
+        if let State::Row = statement.next()? {
+            Ok(Some(statement.read::<Vec<u8>>(0)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn write_key_digest(
+        &self,
+        key_id: &str,
+        digest: &[u8],
+        valid_until: u64,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {KEYS_TABLE_NAME} SET digest = ?, digest_valid_until = ? WHERE key_id = ?"
+        ))?;
+        statement.bind(1, &Value::Binary(digest.to_vec()))?;
+        statement.bind(2, &Value::Integer(valid_until.min(i64::MAX as u64) as i64))?;
+        statement.bind(3, &Value::String(key_id.to_string()))?;
+        statement.next().map(drop)?;
+        Ok(())
+    }
+
+    pub fn get_key_ids_by_prefix(&self, prefix: &str) -> Result<Vec<String>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT key_id FROM {KEYS_TABLE_NAME} \
+                              WHERE key_id >= ?1 AND key_id < ?1 || char(1114111) ORDER BY key_id"
+        ))?;
+        statement.bind(1, &Value::String(prefix.to_string()))?;
+
+        let mut key_ids = vec![];
+        while let State::Row = statement.next()? {
+            key_ids.push(statement.read::<String>(0)?);
+        }
+
+        Ok(key_ids)
+    }
+
+    pub fn get_key_digest(
+        &self,
+        key_id: String,
+        current_timestamp_sec: u64,
+    ) -> Result<Vec<u8>, ServiceError> {
+        if let Some(digest) = self.get_cached_key_digest(&key_id, current_timestamp_sec)? {
+            return Ok(digest);
+        }
+
+        let transfers = self.get_key_transfers(&key_id)?;
+        let (key, records, tombstones) = self.resolve_key(key_id.clone(), current_timestamp_sec)?;
+        let digest = get_key_digest(&key, &transfers, &records, &tombstones);
+
+        // the digest changes when the first of the records or tombstones expires
+        let expiry_policy = load_expiry_policy()?;
+        let records_valid_until = records.iter().map(|r| {
+            r.timestamp_created
+                .saturating_add(expiry_policy.record_ttl(&r.metadata.ttl))
+        });
+        let tombstones_valid_until = tombstones.iter().map(|t| {
+            t.timestamp_issued
+                .saturating_add(expiry_policy.tombstone_retention)
+        });
+        let valid_until = records_valid_until
+            .chain(tombstones_valid_until)
+            .min()
+            .unwrap_or(u64::MAX);
+        self.write_key_digest(&key_id, &digest, valid_until)?;
+
+        Ok(digest)
+    }
+
+    /// Digests of keys with ids starting with `prefix` ordered by key id
+    pub fn get_key_digests(
+        &self,
+        prefix: &str,
+        current_timestamp_sec: u64,
+    ) -> Result<Vec<KeyDigest>, ServiceError> {
+        self.get_key_ids_by_prefix(prefix)?
+            .into_iter()
+            .map(|key_id| {
+                Ok(KeyDigest {
+                    digest: self.get_key_digest(key_id.clone(), current_timestamp_sec)?,
+                    key_id,
+                })
+            })
+            .collect()
+    }
+
+    /// Merkle node of `prefix` and its non-empty children
+    pub fn get_merkle_node(
+        &self,
+        prefix: String,
+        current_timestamp_sec: u64,
+    ) -> Result<(MerkleNode, Vec<MerkleNode>), ServiceError> {
+        let digests = self.get_key_digests(&prefix, current_timestamp_sec)?;
+        let depth = prefix.len();
+
+        let children = group_by_char(&digests, depth)
+            .into_iter()
+            .map(|group| MerkleNode {
+                prefix: group[0].key_id.chars().take(depth + 1).collect(),
+                digest: get_merkle_digest(group, depth + 1),
+                count: group.len() as u64,
+            })
+            .collect();
+
+        let node = MerkleNode {
+            digest: get_merkle_digest(&digests, depth),
+            count: digests.len() as u64,
+            prefix,
+        };
+
+        Ok((node, children))
+    }
+}
@@ -13,8 +13,8 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
-use fluence_keypair::error::DecodingError;
 use marine_sqlite_connector::Error as SqliteError;
+use registry_client::ClientError;
 use thiserror::Error as ThisError;
 
 #[derive(ThisError, Debug)]
@@ -25,6 +25,8 @@ pub enum ServiceError {
         #[source]
         SqliteError,
     ),
+    #[error(transparent)]
+    ClientError(#[from] ClientError),
     #[error("Requested key {0} does not exist")]
     KeyNotExists(String),
     #[error("Key {0} for {1} peer_id already exists with newer timestamp")]
@@ -51,55 +53,14 @@ pub enum ServiceError {
     InvalidWeightTetraplet(String),
     #[error("Invalid weight peer_id: expected {0}, found {1}")]
     InvalidWeightPeerId(String, String),
-    #[error("Invalid signature for key_id {0}; label {1} and peer_id {2}: {3}")]
-    InvalidKeySignature(
-        String,
-        String,
-        String,
-        #[source] fluence_keypair::error::VerificationError,
-    ),
-    #[error("Invalid record metadata signature for key_id {0} and issued by {1}: {2}")]
-    InvalidRecordMetadataSignature(
-        String,
-        String,
-        #[source] fluence_keypair::error::VerificationError,
-    ),
-    #[error("Invalid record signature for key_id {0} and issued by {1}: {2}")]
-    InvalidRecordSignature(
-        String,
-        String,
-        #[source] fluence_keypair::error::VerificationError,
-    ),
-    #[error("Invalid tombstone signature for key_id {0} and issued by {1}: {2}")]
-    InvalidTombstoneSignature(
-        String,
-        String,
-        #[source] fluence_keypair::error::VerificationError,
-    ),
-    #[error("Key can't be registered in the future")]
-    InvalidKeyTimestamp,
-    #[error("Record metadata can't be issued in the future")]
-    InvalidRecordMetadataTimestamp,
-    #[error("Record can't be registered in the future")]
-    InvalidRecordTimestamp,
     #[error("Record is already expired")]
     RecordAlreadyExpired,
-    #[error("Tombstone can't be issued in the future")]
-    InvalidTombstoneTimestamp,
+    #[error("Invalid ttl for key_id {0} and issued by {1}: expected a single positive value")]
+    InvalidRecordTtl(String, String),
     #[error("Records to publish should belong to one key id")]
     RecordsPublishingError,
     #[error("Tombstones to publish should belong to one key id")]
     TombstonesPublishingError,
-    #[error("peer id parse error: {0}")]
-    PeerIdParseError(String),
-    #[error("public key extraction from peer id failed: {0}")]
-    PublicKeyExtractionError(String),
-    #[error("{0}")]
-    PublicKeyDecodeError(
-        #[from]
-        #[source]
-        DecodingError,
-    ),
     #[error("Weight for record with peer_id {0} and set_by {1} is missing ")]
     MissingRecordWeight(String, String),
     #[error("merge_keys: keys argument is empty")]
@@ -108,4 +69,52 @@ pub enum ServiceError {
         "Newer record or tombstone for key_id: {0}, issued_by: {1}, peer_id: {2} already exists"
     )]
     NewerRecordOrTombstoneExists(String, String, String),
+    #[error("Key tombstone can't be issued in the future")]
+    InvalidKeyTombstoneTimestamp,
+    #[error("Invalid key tombstone: key_id {0} doesn't match label {1} and owner_peer_id {2}")]
+    InvalidKeyTombstoneKeyId(String, String, String),
+    #[error("Invalid key tombstone signature for key_id {0} and owner_peer_id {1}: {2}")]
+    InvalidKeyTombstoneSignature(
+        String,
+        String,
+        #[source] fluence_keypair::error::VerificationError,
+    ),
+    #[error("Key tombstone for key_id {0} does not exist")]
+    KeyTombstoneNotExists(String),
+    #[error("Key {0} was removed by its owner")]
+    KeyTombstoned(String),
+    #[error("Newer key tombstone for key_id {0} already exists")]
+    NewerKeyTombstoneExists(String),
+    #[error("Unknown challenge type: {0}")]
+    UnknownChallengeType(String),
+    #[error("Invalid challenge for key_id {0}: {1}")]
+    InvalidChallenge(String, String),
+    #[error("Peer {1} has no permission to publish for key_id {0}: {2}")]
+    PermissionDenied(String, String, String),
+    #[error("Key {0} is not stored and its tombstone comes without the key transfer, the signer is unknown")]
+    KeyTombstoneSignerUnknown(String),
+    #[error("Unknown label match type: {0}, expected exact, prefix or substring")]
+    UnknownLabelMatchType(String),
+    #[error("Too many keys requested: {0}, the limit is {1}")]
+    TooManyKeys(usize, u64),
+    #[error("Table {0} has unexpected schema and can't be migrated: {1}")]
+    UnexpectedTableSchema(String, String),
+    #[error("Migration to schema version {0} ({1}) failed: {2}")]
+    MigrationFailed(u64, String, String),
+    #[error("Peer {0} is not allowed to change the registry configuration")]
+    NotAdmin(String),
+    #[error("Only the host peer can change the registry admins, called by {0}")]
+    NotHost(String),
+    #[error("Failed to access registry config {0}: {1}")]
+    ConfigFileError(String, String),
+    #[error("Invalid registry config: {0}")]
+    InvalidConfig(String),
+    #[error("Unknown eviction policy: {0}, expected weight, recency or issuer_diversity")]
+    UnknownEvictionPolicy(String),
+    #[error("Peer {1} is not the owner of key_id {0}")]
+    NotKeyOwner(String, String),
+    #[error("Records limit {0} exceeds the maximum {1}")]
+    RecordsLimitTooLarge(u64, u64),
+    #[error("Peer {0} exceeded {1} quota: {2} writes per {3} seconds")]
+    QuotaExceeded(String, String, u64, u64),
 }
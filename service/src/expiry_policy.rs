@@ -0,0 +1,34 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::config::{load_config, Config};
+use crate::error::ServiceError;
+pub use registry_client::ExpiryPolicy;
+
+pub fn load_expiry_policy() -> Result<ExpiryPolicy, ServiceError> {
+    Ok(ExpiryPolicy::from(&load_config()?))
+}
+
+impl From<&Config> for ExpiryPolicy {
+    fn from(config: &Config) -> Self {
+        Self {
+            expired_timeout: config.expired_timeout,
+            stale_timeout: config.stale_timeout,
+            max_record_ttl: config.max_record_ttl,
+            tombstone_retention: config.tombstone_retention,
+        }
+    }
+}
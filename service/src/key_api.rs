@@ -13,10 +13,20 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use crate::config::load_config;
+use crate::defaults::{KEYS_PAGE_SIZE_LIMIT, QUOTA_KIND_KEY_WRITES};
 use crate::error::ServiceError;
+use crate::error::ServiceError::{NotKeyOwner, RecordsLimitTooLarge};
 use crate::key::{Key, KeyInternal};
-use crate::misc::check_weight_result;
-use crate::results::{GetKeyMetadataResult, MergeKeysResult, RegisterKeyResult, RegistryResult};
+use crate::key_transfer::{
+    merge_transfer_chains, verify_key_signature, verify_transfer_chain, KeyTransfer,
+};
+use crate::misc::{check_weight_result, get_initiator_weight};
+use crate::permissions::{check_challenge, delegation_bytes};
+use crate::results::{
+    GetKeyMetadataResult, GetKeyTransferResult, GetKeysResult, MergeKeysResult, RegisterKeyResult,
+    RegistryResult, ResolveKeysResult, ResolvedKey, SearchKeysResult,
+};
 use crate::storage_impl::get_storage;
 use crate::tetraplets_checkers::{check_timestamp_tetraplets, check_weight_tetraplets};
 use crate::{wrapped_try, WeightResult};
@@ -48,6 +58,13 @@ pub fn get_key_id(label: String, peer_id: String) -> String {
     Key::get_id(&label, &peer_id)
 }
 
+/// bytes to be signed by the key owner to allow `issued_by` to publish for the key
+/// with the `delegation` challenge type, the signature is used as a solution
+#[marine]
+pub fn get_delegation_bytes(key_id: String, issued_by: String) -> Vec<u8> {
+    delegation_bytes(&key_id, &issued_by)
+}
+
 /// register new key if not exists with caller peer_id, update if exists with same peer_id or return error
 #[marine]
 pub fn register_key(
@@ -69,6 +86,8 @@ pub fn register_key(
             .unwrap_or(&call_parameters.init_peer_id)
             .clone();
         check_weight_result(&owner_peer_id, &weight)?;
+        let config = load_config()?;
+        let storage = get_storage()?;
         let key = Key::new(
             label,
             owner_peer_id,
@@ -77,18 +96,29 @@ pub fn register_key(
             challenge_type,
             signature,
         );
-        key.verify(current_timestamp_sec)?;
+        // the write is counted against the quota only if the key is stored
+        storage.transaction(|| {
+            storage.consume_write_quota(
+                &config,
+                &call_parameters,
+                QUOTA_KIND_KEY_WRITES,
+                1,
+                get_initiator_weight(&call_parameters.init_peer_id, &weight),
+                current_timestamp_sec,
+            )?;
+            key.verify_timestamp(current_timestamp_sec)?;
+            storage.verify_key_signature(&key, config.accept_v1_signatures)?;
+            check_challenge(&key)?;
 
-        let key_id = key.id.clone();
-        let weight = weight.weight;
-        let storage = get_storage()?;
-        storage.update_key(KeyInternal {
-            key,
-            timestamp_published: 0,
-            weight,
-        })?;
+            let key_id = key.id.clone();
+            storage.update_key(KeyInternal {
+                key,
+                timestamp_published: 0,
+                weight: weight.weight,
+            })?;
 
-        Ok(key_id)
+            Ok(key_id)
+        })
     })
     .into()
 }
@@ -98,6 +128,32 @@ pub fn get_key_metadata(key_id: String) -> GetKeyMetadataResult {
     wrapped_try(|| get_storage()?.get_key(key_id)).into()
 }
 
+/// Return metadata, records and tombstones for each key in the same order,
+/// a missing key doesn't fail the whole batch
+#[marine]
+pub fn resolve_keys(key_ids: Vec<String>, current_timestamp_sec: u64) -> ResolveKeysResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 1)?;
+        if key_ids.len() as u64 > KEYS_PAGE_SIZE_LIMIT {
+            return Err(ServiceError::TooManyKeys(
+                key_ids.len(),
+                KEYS_PAGE_SIZE_LIMIT,
+            ));
+        }
+
+        let storage = get_storage()?;
+        Ok(key_ids
+            .into_iter()
+            .map(|key_id| {
+                let result = storage.resolve_key(key_id.clone(), current_timestamp_sec);
+                ResolvedKey::new(key_id, result)
+            })
+            .collect())
+    })
+    .into()
+}
+
 /// Used for replication, same as register_key, updates timestamp_accessed
 #[marine]
 pub fn republish_key(
@@ -110,19 +166,167 @@ pub fn republish_key(
         check_weight_tetraplets(&call_parameters, 1, 0)?;
         check_weight_result(&key.owner_peer_id, &weight)?;
         check_timestamp_tetraplets(&call_parameters, 2)?;
-        key.verify(current_timestamp_sec)?;
 
         // just to be sure
         key.id = Key::get_id(&key.label, &key.owner_peer_id);
 
+        key.verify_timestamp(current_timestamp_sec)?;
         let storage = get_storage()?;
+        storage.verify_key_signature(&key, load_config()?.accept_v1_signatures)?;
+        check_challenge(&key)?;
+
         match storage.update_key(KeyInternal {
             key,
             timestamp_published: current_timestamp_sec,
             weight: weight.weight,
         }) {
-            // we should ignore this error for republish
+            // we should ignore these errors for republish
             Err(ServiceError::KeyAlreadyExistsNewerTimestamp(_, _)) => Ok(()),
+            Err(ServiceError::KeyTombstoned(_)) => Ok(()),
+            other => other,
+        }
+    })
+    .into()
+}
+
+/// list keys currently owned by `owner_peer_id` page by page, `cursor` is empty for the first page
+/// and `next_cursor` of the previous page for the following ones. `limit` is capped by
+/// `KEYS_PAGE_SIZE_LIMIT`, 0 means the maximum page size
+#[marine]
+pub fn get_keys_by_owner(owner_peer_id: String, cursor: String, limit: u64) -> GetKeysResult {
+    wrapped_try(|| get_storage()?.get_keys_by_owner(owner_peer_id, cursor, limit)).into()
+}
+
+/// find keys by label with `match_type` exact, prefix or substring, paginated as `get_keys_by_owner`
+#[marine]
+pub fn search_keys(
+    query: String,
+    match_type: String,
+    cursor: String,
+    limit: u64,
+) -> SearchKeysResult {
+    wrapped_try(|| get_storage()?.search_keys(query, &match_type, cursor, limit)).into()
+}
+
+#[marine]
+pub fn get_key_transfer_bytes(
+    key_id: String,
+    mut issued_by: Vec<String>,
+    new_owner_peer_id: String,
+    timestamp_issued: u64,
+) -> Vec<u8> {
+    KeyTransfer {
+        key_id,
+        issued_by: issued_by
+            .pop()
+            .unwrap_or(marine_rs_sdk::get_call_parameters().init_peer_id),
+        new_owner_peer_id,
+        timestamp_issued,
+        ..Default::default()
+    }
+    .signature_bytes()
+}
+
+/// hand the key over to `new_owner_peer_id`, should be signed by the current key owner.
+/// After the transfer the key should be re-registered by the new owner
+#[marine]
+pub fn transfer_key(
+    key_id: String,
+    issued_by: Vec<String>,
+    new_owner_peer_id: String,
+    timestamp_issued: u64,
+    signature: Vec<u8>,
+    current_timestamp_sec: u64,
+) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 5)?;
+        let issued_by = issued_by
+            .get(0)
+            .unwrap_or(&call_parameters.init_peer_id)
+            .clone();
+        let transfer = KeyTransfer {
+            key_id,
+            issued_by,
+            new_owner_peer_id,
+            timestamp_issued,
+            signature,
+        };
+        transfer.verify(current_timestamp_sec)?;
+
+        get_storage()?.update_key_transfer(transfer)
+    })
+    .into()
+}
+
+/// Set the limit of non-host records for the key, can be called only by the key owner.
+/// The limit can't exceed `max_key_records_limit` of the host, 0 resets it to the host default
+#[marine]
+pub fn set_key_records_limit(key_id: String, limit: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        let storage = get_storage()?;
+        let key = storage.get_key(key_id.clone())?;
+        if storage.get_key_owner(&key)? != call_parameters.init_peer_id {
+            return Err(NotKeyOwner(key_id, call_parameters.init_peer_id));
+        }
+
+        let max_limit = load_config()?.max_key_records_limit;
+        if limit > max_limit {
+            return Err(RecordsLimitTooLarge(limit, max_limit));
+        }
+
+        storage.set_key_records_limit(&key_id, (limit != 0).then_some(limit))
+    })
+    .into()
+}
+
+#[marine]
+pub fn get_key_transfer(key_id: String) -> GetKeyTransferResult {
+    wrapped_try(|| get_storage()?.get_key_transfer(&key_id)).into()
+}
+
+/// Used for replication of transferred keys, same as republish_key, but also stores the transfer chain
+/// ordered by `timestamp_issued`. The chain is required to verify key versions signed by later owners
+#[marine]
+pub fn republish_key_transfer(
+    mut key: Key,
+    transfers: Vec<KeyTransfer>,
+    weight: WeightResult,
+    current_timestamp_sec: u64,
+) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_weight_tetraplets(&call_parameters, 2, 0)?;
+        check_weight_result(&key.owner_peer_id, &weight)?;
+        check_timestamp_tetraplets(&call_parameters, 3)?;
+
+        // just to be sure
+        key.id = Key::get_id(&key.label, &key.owner_peer_id);
+
+        verify_transfer_chain(
+            &key.id,
+            &key.owner_peer_id,
+            &transfers,
+            current_timestamp_sec,
+        )?;
+        key.verify_timestamp(current_timestamp_sec)?;
+
+        let storage = get_storage()?;
+        let transfers = merge_transfer_chains(storage.get_key_transfers(&key.id)?, transfers);
+        verify_key_signature(&key, &transfers, load_config()?.accept_v1_signatures)?;
+        check_challenge(&key)?;
+
+        match storage.update_key_with_transfers(
+            KeyInternal {
+                key,
+                timestamp_published: current_timestamp_sec,
+                weight: weight.weight,
+            },
+            transfers,
+        ) {
+            // we should ignore these errors for republish
+            Err(ServiceError::KeyTombstoned(_)) => Ok(()),
             other => other,
         }
     })
@@ -14,16 +14,21 @@
  * limitations under the License.
  */
 
-use crate::defaults::KEYS_TABLE_NAME;
+use crate::defaults::{
+    CHANGE_EXPIRED, CHANGE_KEY_REGISTERED, KEYS_PAGE_SIZE_LIMIT, KEYS_TABLE_NAME,
+    LABEL_MATCH_EXACT, LABEL_MATCH_PREFIX, LABEL_MATCH_SUBSTRING,
+};
 
 use crate::error::ServiceError;
-use crate::error::ServiceError::{InternalError, KeyNotExists};
+use crate::error::ServiceError::{InternalError, KeyNotExists, UnknownLabelMatchType};
 use crate::key::{Key, KeyInternal};
 use crate::storage_impl::Storage;
 use marine_sqlite_connector::{State, Statement, Value};
 
 impl Storage {
-    pub fn create_key_tables(&self) {
+    /// Create keys table on a fresh database. The table of a pre-migrations database is kept
+    /// only if it has exactly the same schema, otherwise the error is returned.
+    pub fn create_key_tables(&self) -> Result<(), ServiceError> {
         let table_schema = f!("
             CREATE TABLE IF NOT EXISTS {KEYS_TABLE_NAME} (
                 key_id TEXT PRIMARY KEY,
@@ -37,19 +42,9 @@ impl Storage {
                 weight INTEGER
             );
         ");
-        let current_table_schema = self
-            .get_table_schema(KEYS_TABLE_NAME.to_string())
-            .expect(f!("failed to get {KEYS_TABLE_NAME} table schema").as_str());
-        if !current_table_schema.is_empty() && table_schema != current_table_schema {
-            self.delete_table(KEYS_TABLE_NAME.to_string())
-                .expect(f!("failed to delete {KEYS_TABLE_NAME} table").as_str())
-        }
-
-        let result = self.connection.execute(table_schema);
-
-        if let Err(error) = result {
-            println!("create_keys_table error: {}", error);
-        }
+        self.check_table_schema(KEYS_TABLE_NAME, &table_schema)?;
+        self.connection.execute(table_schema)?;
+        Ok(())
     }
 
     pub fn get_key(&self, key_id: String) -> Result<Key, ServiceError> {
@@ -66,9 +61,169 @@ impl Storage {
         }
     }
 
+    /// Columns for the latest link of the key transfer chain, they are kept on key updates.
+    /// The whole chain is stored in the key transfers table, see `create_key_transfers_table`
+    pub fn add_key_transfer_columns(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            ALTER TABLE {KEYS_TABLE_NAME} ADD COLUMN transfer_issued_by TEXT;
+            ALTER TABLE {KEYS_TABLE_NAME} ADD COLUMN transfer_new_owner_peer_id TEXT;
+            ALTER TABLE {KEYS_TABLE_NAME} ADD COLUMN transfer_timestamp_issued INTEGER;
+            ALTER TABLE {KEYS_TABLE_NAME} ADD COLUMN transfer_signature BLOB;
+        "))?;
+        Ok(())
+    }
+
+    pub fn add_key_records_limit_column(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!(
+            "ALTER TABLE {KEYS_TABLE_NAME} ADD COLUMN records_limit INTEGER;"
+        ))?;
+        Ok(())
+    }
+
+    /// Set the records limit of the key, `None` means the global limit
+    pub fn set_key_records_limit(
+        &self,
+        key_id: &str,
+        limit: Option<u64>,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {KEYS_TABLE_NAME} SET records_limit = ? WHERE key_id = ?"
+        ))?;
+        statement.bind(
+            1,
+            &limit.map_or(Value::Null, |limit| Value::Integer(limit as i64)),
+        )?;
+        statement.bind(2, &Value::String(key_id.to_string()))?;
+        statement.next().map(drop)?;
+
+        if self.connection.changes() == 1 {
+            Ok(())
+        } else {
+            Err(KeyNotExists(key_id.to_string()))
+        }
+    }
+
+    pub fn get_key_records_limit(&self, key_id: &str) -> Result<Option<u64>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT records_limit FROM {KEYS_TABLE_NAME} WHERE key_id = ?"
+        ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<Option<i64>>(0)?.map(|limit| limit as u64))
+        } else {
+            Err(KeyNotExists(key_id.to_string()))
+        }
+    }
+
+    pub fn create_key_owner_index(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE INDEX IF NOT EXISTS keys_owner_index
+                ON {KEYS_TABLE_NAME} (owner_peer_id, key_id);
+            CREATE INDEX IF NOT EXISTS keys_transfer_owner_index
+                ON {KEYS_TABLE_NAME} (transfer_new_owner_peer_id, key_id);
+        "))?;
+        Ok(())
+    }
+
+    /// Keys currently owned by `owner_peer_id` ordered by key id, starting after `cursor`.
+    /// Returns the page and the cursor for the next page, which is empty if there are no more keys
+    pub fn get_keys_by_owner(
+        &self,
+        owner_peer_id: String,
+        cursor: String,
+        limit: u64,
+    ) -> Result<(Vec<Key>, String), ServiceError> {
+        let limit = get_page_size(limit);
+        let mut statement = self.connection.prepare(f!(
+            "SELECT key_id, label, owner_peer_id, timestamp_created, challenge, challenge_type, signature \
+                              FROM {KEYS_TABLE_NAME} \
+                              WHERE key_id > ? AND (transfer_new_owner_peer_id = ? \
+                                OR (transfer_new_owner_peer_id IS NULL AND owner_peer_id = ?)) \
+                              ORDER BY key_id LIMIT ?"
+        ))?;
+        statement.bind(1, &Value::String(cursor))?;
+        statement.bind(2, &Value::String(owner_peer_id.clone()))?;
+        statement.bind(3, &Value::String(owner_peer_id))?;
+        // one more key to check if there is the next page
+        statement.bind(4, &Value::Integer(limit as i64 + 1))?;
+
+        let mut keys: Vec<Key> = vec![];
+        while let State::Row = statement.next()? {
+            keys.push(read_key(&statement)?);
+        }
+
+        let next_cursor = split_page(&mut keys, limit, |k| &k.id);
+        Ok((keys, next_cursor))
+    }
+
+    pub fn create_key_label_index(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE INDEX IF NOT EXISTS keys_label_index ON {KEYS_TABLE_NAME} (label, key_id);
+        "))?;
+        Ok(())
+    }
+
+    /// Keys with labels matching `query` ordered by key id, starting after `cursor`.
+    /// Exact and prefix matches use the label index, substring match scans the whole table.
+    /// Returns the page and the cursor for the next page, which is empty if there are no more keys
+    pub fn search_keys(
+        &self,
+        query: String,
+        match_type: &str,
+        cursor: String,
+        limit: u64,
+    ) -> Result<(Vec<KeyInternal>, String), ServiceError> {
+        let condition = match match_type {
+            LABEL_MATCH_EXACT => "label = ?1",
+            // labels starting with the query are between the query and the query followed by
+            // the greatest char in the binary collation
+            LABEL_MATCH_PREFIX => "label >= ?1 AND label < ?1 || char(1114111)",
+            LABEL_MATCH_SUBSTRING => "instr(label, ?1) > 0",
+            other => return Err(UnknownLabelMatchType(other.to_string())),
+        };
+
+        let limit = get_page_size(limit);
+        let mut statement = self.connection.prepare(f!(
+            "SELECT key_id, label, owner_peer_id, timestamp_created, challenge, challenge_type, signature, timestamp_published, weight \
+                              FROM {KEYS_TABLE_NAME} WHERE {condition} AND key_id > ?2 \
+                              ORDER BY key_id LIMIT ?3"
+        ))?;
+        statement.bind(1, &Value::String(query))?;
+        statement.bind(2, &Value::String(cursor))?;
+        // one more key to check if there is the next page
+        statement.bind(3, &Value::Integer(limit as i64 + 1))?;
+
+        let mut keys: Vec<KeyInternal> = vec![];
+        while let State::Row = statement.next()? {
+            keys.push(read_internal_key(&statement)?);
+        }
+
+        let next_cursor = split_page(&mut keys, limit, |k| &k.key.id);
+        Ok((keys, next_cursor))
+    }
+
     pub fn write_key(&self, key: KeyInternal) -> Result<(), ServiceError> {
+        let (is_new, is_stored) = match self.get_key(key.key.id.clone()) {
+            Ok(existing) => (
+                false,
+                existing.timestamp_created == key.key.timestamp_created
+                    && existing.signature == key.key.signature,
+            ),
+            Err(KeyNotExists(_)) => (true, false),
+            Err(e) => return Err(e),
+        };
+        let key_id = key.key.id.clone();
+
         let mut statement = self.connection.prepare(f!("
-             INSERT OR REPLACE INTO {KEYS_TABLE_NAME} VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);
+             INSERT INTO {KEYS_TABLE_NAME} \
+                (key_id, label, owner_peer_id, timestamp_created, challenge, challenge_type, signature, timestamp_published, weight) \
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT(key_id) DO UPDATE SET \
+                label = excluded.label, owner_peer_id = excluded.owner_peer_id, \
+                timestamp_created = excluded.timestamp_created, challenge = excluded.challenge, \
+                challenge_type = excluded.challenge_type, signature = excluded.signature, \
+                timestamp_published = excluded.timestamp_published, weight = excluded.weight;
          "))?;
 
         statement.bind(1, &Value::String(key.key.id))?;
@@ -81,10 +236,21 @@ impl Storage {
         statement.bind(8, &Value::Integer(key.timestamp_published as i64))?;
         statement.bind(9, &Value::Integer(key.weight as i64))?;
         statement.next()?;
+
+        // the transfer chain outlives the key removed by a key tombstone
+        if is_new {
+            self.update_latest_key_transfer(&key_id)?;
+        }
+
+        if !is_stored {
+            self.log_change(&key_id, CHANGE_KEY_REGISTERED, "", "")?;
+        }
         Ok(())
     }
 
     pub fn update_key(&self, key: KeyInternal) -> Result<(), ServiceError> {
+        self.check_key_tombstone(&key.key.id, key.key.timestamp_created)?;
+
         if let Ok(existing_key) = self.get_key(key.key.id.clone()) {
             if existing_key.timestamp_created > key.key.timestamp_created {
                 return Err(ServiceError::KeyAlreadyExistsNewerTimestamp(
@@ -162,7 +328,8 @@ impl Storage {
 
         let removed_keys = expired_keys.len();
         for id in expired_keys.into_iter() {
-            self.delete_key(id)?;
+            self.delete_key(id.clone())?;
+            self.log_change(&id, CHANGE_EXPIRED, "", "")?;
         }
 
         Ok(removed_keys as u64)
@@ -188,3 +355,22 @@ pub fn read_internal_key(statement: &Statement) -> Result<KeyInternal, ServiceEr
         weight: statement.read::<i64>(8)? as u32,
     })
 }
+
+/// 0 means the maximum page size
+fn get_page_size(limit: u64) -> u64 {
+    if limit == 0 || limit > KEYS_PAGE_SIZE_LIMIT {
+        KEYS_PAGE_SIZE_LIMIT
+    } else {
+        limit
+    }
+}
+
+/// Cut the extra item selected over the page size and return the next page cursor
+fn split_page<T>(items: &mut Vec<T>, limit: u64, get_id: impl Fn(&T) -> &String) -> String {
+    if items.len() as u64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(|i| get_id(i).clone()).unwrap_or_default()
+    } else {
+        "".to_string()
+    }
+}
@@ -0,0 +1,100 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::error::ServiceError;
+use crate::key::Key;
+use marine_rs_sdk::marine;
+use registry_client::extract_public_key;
+use registry_client::signing::{verify_v2_signature, CanonicalEncoder, DOMAIN_KEY_TOMBSTONE};
+
+/// Removes the key with all its records and tombstones, issued by the key owner
+#[marine]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct KeyTombstone {
+    /// base58-encoded key id
+    pub key_id: String,
+    /// label of the removed key
+    pub label: String,
+    /// peer id of the key owner in base58
+    pub owner_peer_id: String,
+    /// timestamp in seconds, all versions of the key created before it are removed
+    pub timestamp_issued: u64,
+    /// encoded and hashed previous fields signed by `owner_peer_id`
+    pub signature: Vec<u8>,
+}
+
+#[derive(Default, Clone)]
+pub struct KeyTombstoneInternal {
+    pub tombstone: KeyTombstone,
+    /// timestamp of last publishing in seconds
+    pub timestamp_published: u64,
+}
+
+impl KeyTombstone {
+    pub fn new(
+        label: String,
+        owner_peer_id: String,
+        timestamp_issued: u64,
+        signature: Vec<u8>,
+    ) -> Self {
+        let key_id = Key::get_id(&label, &owner_peer_id);
+
+        Self {
+            key_id,
+            label,
+            owner_peer_id,
+            timestamp_issued,
+            signature,
+        }
+    }
+
+    pub fn signature_bytes(&self) -> Vec<u8> {
+        CanonicalEncoder::new(DOMAIN_KEY_TOMBSTONE)
+            .string(&self.key_id)
+            .string(&self.label)
+            .string(&self.owner_peer_id)
+            .u64(self.timestamp_issued)
+            .finish()
+    }
+
+    /// `signer_peer_id` is one of the key owners, it differs from `owner_peer_id` after key transfer
+    pub fn verify(
+        &self,
+        current_timestamp_sec: u64,
+        signer_peer_id: &str,
+    ) -> Result<(), ServiceError> {
+        if self.timestamp_issued > current_timestamp_sec {
+            return Err(ServiceError::InvalidKeyTombstoneTimestamp);
+        }
+
+        if self.key_id != Key::get_id(&self.label, &self.owner_peer_id) {
+            return Err(ServiceError::InvalidKeyTombstoneKeyId(
+                self.key_id.clone(),
+                self.label.clone(),
+                self.owner_peer_id.clone(),
+            ));
+        }
+
+        let pk = extract_public_key(signer_peer_id.to_string())?;
+        verify_v2_signature(&pk, &self.signature, &self.signature_bytes()).map_err(|e| {
+            ServiceError::InvalidKeyTombstoneSignature(
+                self.key_id.clone(),
+                signer_peer_id.to_string(),
+                e,
+            )
+        })
+    }
+}
@@ -0,0 +1,165 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::config::load_config;
+use crate::defaults::QUOTA_KIND_KEY_WRITES;
+use crate::error::ServiceError;
+use crate::key_tombstone::{KeyTombstone, KeyTombstoneInternal};
+use crate::key_transfer::KeyTransfer;
+use crate::misc::{check_weight_result, get_initiator_weight};
+use crate::results::{GetKeyTombstoneResult, RegistryResult};
+use crate::storage_impl::get_storage;
+use crate::tetraplets_checkers::{check_timestamp_tetraplets, check_weight_tetraplets};
+use crate::{wrapped_try, WeightResult};
+use marine_rs_sdk::{marine, CallParameters};
+
+#[marine]
+pub fn get_key_tombstone_bytes(
+    label: String,
+    mut owner_peer_id: Vec<String>,
+    timestamp_issued: u64,
+) -> Vec<u8> {
+    KeyTombstone::new(
+        label,
+        owner_peer_id
+            .pop()
+            .unwrap_or(marine_rs_sdk::get_call_parameters().init_peer_id),
+        timestamp_issued,
+        vec![],
+    )
+    .signature_bytes()
+}
+
+/// remove the key with all its records and tombstones, should be signed by the key owner
+#[marine]
+pub fn add_key_tombstone(
+    label: String,
+    owner_peer_id: Vec<String>,
+    timestamp_issued: u64,
+    signature: Vec<u8>,
+    current_timestamp_sec: u64,
+) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 4)?;
+        let owner_peer_id = owner_peer_id
+            .get(0)
+            .unwrap_or(&call_parameters.init_peer_id)
+            .clone();
+        let tombstone = KeyTombstone::new(label, owner_peer_id, timestamp_issued, signature);
+        check_and_add_key_tombstone(&call_parameters, tombstone, 0, current_timestamp_sec)
+    })
+    .into()
+}
+
+/// Same as add_key_tombstone, but the write quota of the initiator is relaxed by its `weight`
+#[marine]
+pub fn add_key_tombstone_weighted(
+    label: String,
+    owner_peer_id: Vec<String>,
+    timestamp_issued: u64,
+    signature: Vec<u8>,
+    weight: WeightResult,
+    current_timestamp_sec: u64,
+) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_weight_tetraplets(&call_parameters, 4, 0)?;
+        check_timestamp_tetraplets(&call_parameters, 5)?;
+        let owner_peer_id = owner_peer_id
+            .get(0)
+            .unwrap_or(&call_parameters.init_peer_id)
+            .clone();
+        check_weight_result(&owner_peer_id, &weight)?;
+        let tombstone = KeyTombstone::new(label, owner_peer_id, timestamp_issued, signature);
+        check_and_add_key_tombstone(
+            &call_parameters,
+            tombstone,
+            get_initiator_weight(&call_parameters.init_peer_id, &weight),
+            current_timestamp_sec,
+        )
+    })
+    .into()
+}
+
+/// the write is counted against the quota only if the key tombstone is stored
+fn check_and_add_key_tombstone(
+    call_parameters: &CallParameters,
+    tombstone: KeyTombstone,
+    initiator_weight: u32,
+    current_timestamp_sec: u64,
+) -> Result<(), ServiceError> {
+    let config = load_config()?;
+    let storage = get_storage()?;
+    storage.transaction(|| {
+        storage.consume_write_quota(
+            &config,
+            call_parameters,
+            QUOTA_KIND_KEY_WRITES,
+            1,
+            initiator_weight,
+            current_timestamp_sec,
+        )?;
+        let transfers = storage.verify_key_tombstone(&tombstone, vec![], current_timestamp_sec)?;
+
+        storage.update_key_tombstone(
+            KeyTombstoneInternal {
+                tombstone,
+                timestamp_published: 0,
+            },
+            transfers,
+        )
+    })
+}
+
+#[marine]
+pub fn get_key_tombstone(key_id: String) -> GetKeyTombstoneResult {
+    wrapped_try(|| get_storage()?.get_key_tombstone(key_id)).into()
+}
+
+/// Used for replication, same as add_key_tombstone, updates timestamp_published.
+/// `transfers` is the key transfer chain the tombstone was verified with,
+/// peers without the key accept only tombstones with transfer chains
+#[marine]
+pub fn republish_key_tombstone(
+    tombstone: KeyTombstone,
+    transfers: Vec<KeyTransfer>,
+    current_timestamp_sec: u64,
+) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 2)?;
+        let storage = get_storage()?;
+        // stored tombstone was verified with the stored transfer chain
+        let transfers = if storage.is_key_tombstone_stored(&tombstone)? {
+            storage.get_key_transfers(&tombstone.key_id)?
+        } else {
+            storage.verify_key_tombstone(&tombstone, transfers, current_timestamp_sec)?
+        };
+
+        match storage.update_key_tombstone(
+            KeyTombstoneInternal {
+                tombstone,
+                timestamp_published: current_timestamp_sec,
+            },
+            transfers,
+        ) {
+            // we should ignore this error for republish
+            Err(ServiceError::NewerKeyTombstoneExists(_)) => Ok(()),
+            other => other,
+        }
+    })
+    .into()
+}
@@ -0,0 +1,226 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::defaults::{CHANGE_KEY_REMOVED, KEY_TOMBSTONES_TABLE_NAME};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{
+    KeyNotExists, KeyTombstoneNotExists, KeyTombstoneSignerUnknown, KeyTombstoned,
+    NewerKeyTombstoneExists,
+};
+use crate::key_tombstone::{KeyTombstone, KeyTombstoneInternal};
+use crate::key_transfer::{
+    get_key_owner_at, merge_transfer_chains, verify_transfer_chain, KeyTransfer,
+};
+use crate::storage_impl::Storage;
+use marine_sqlite_connector::{State, Statement, Value};
+
+impl Storage {
+    pub fn create_key_tombstones_table(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE TABLE IF NOT EXISTS {KEY_TOMBSTONES_TABLE_NAME} (
+                key_id TEXT PRIMARY KEY,
+                label TEXT,
+                owner_peer_id TEXT,
+                timestamp_issued INTEGER NOT NULL,
+                signature BLOB NOT NULL,
+                timestamp_published INTEGER
+            );
+        "))?;
+        Ok(())
+    }
+
+    /// Columns for the key transfer the tombstone was verified with.
+    /// Superseded by the key transfers table keeping the whole chain, see `create_key_transfers_table`
+    pub fn add_key_tombstone_transfer_columns(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            ALTER TABLE {KEY_TOMBSTONES_TABLE_NAME} ADD COLUMN transfer_issued_by TEXT;
+            ALTER TABLE {KEY_TOMBSTONES_TABLE_NAME} ADD COLUMN transfer_new_owner_peer_id TEXT;
+            ALTER TABLE {KEY_TOMBSTONES_TABLE_NAME} ADD COLUMN transfer_timestamp_issued INTEGER;
+            ALTER TABLE {KEY_TOMBSTONES_TABLE_NAME} ADD COLUMN transfer_signature BLOB;
+        "))?;
+        Ok(())
+    }
+
+    pub fn get_key_tombstone(&self, key_id: String) -> Result<KeyTombstone, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT key_id, label, owner_peer_id, timestamp_issued, signature \
+                              FROM {KEY_TOMBSTONES_TABLE_NAME} WHERE key_id = ?"
+        ))?;
+        statement.bind(1, &Value::String(key_id.clone()))?;
+
+        if let State::Row = statement.next()? {
+            read_key_tombstone(&statement)
+        } else {
+            Err(KeyTombstoneNotExists(key_id))
+        }
+    }
+
+    /// Verify the tombstone by the key owner at `timestamp_issued`, see `get_key_owner_at`.
+    /// The received transfer chain is merged with the stored one, a tombstone of the key
+    /// which is not stored should come with the chain, `owner_peer_id` of the tombstone itself
+    /// can't be trusted. Returns the chain the tombstone was verified with
+    pub fn verify_key_tombstone(
+        &self,
+        tombstone: &KeyTombstone,
+        transfers: Vec<KeyTransfer>,
+        current_timestamp_sec: u64,
+    ) -> Result<Vec<KeyTransfer>, ServiceError> {
+        verify_transfer_chain(
+            &tombstone.key_id,
+            &tombstone.owner_peer_id,
+            &transfers,
+            current_timestamp_sec,
+        )?;
+
+        let stored = self.get_key_transfers(&tombstone.key_id)?;
+        if stored.is_empty() && transfers.is_empty() {
+            match self.check_key_existence(&tombstone.key_id) {
+                Err(KeyNotExists(key_id)) => return Err(KeyTombstoneSignerUnknown(key_id)),
+                other => other?,
+            }
+        }
+        let transfers = merge_transfer_chains(stored, transfers);
+
+        let signer = get_key_owner_at(
+            &tombstone.owner_peer_id,
+            tombstone.timestamp_issued,
+            &transfers,
+        );
+        tombstone.verify(current_timestamp_sec, &signer)?;
+        Ok(transfers)
+    }
+
+    /// Check if exactly the same tombstone is already stored
+    pub fn is_key_tombstone_stored(&self, tombstone: &KeyTombstone) -> Result<bool, ServiceError> {
+        match self.get_key_tombstone(tombstone.key_id.clone()) {
+            Ok(stored) => Ok(stored == *tombstone),
+            Err(KeyTombstoneNotExists(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Return error if the key version created at `timestamp_created` is removed by a key tombstone
+    pub fn check_key_tombstone(
+        &self,
+        key_id: &str,
+        timestamp_created: u64,
+    ) -> Result<(), ServiceError> {
+        match self.get_key_tombstone(key_id.to_string()) {
+            Ok(tombstone) if tombstone.timestamp_issued >= timestamp_created => {
+                Err(KeyTombstoned(key_id.to_string()))
+            }
+            Ok(_) | Err(KeyTombstoneNotExists(_)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn write_key_tombstone(&self, tombstone: KeyTombstoneInternal) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!("
+             INSERT OR REPLACE INTO {KEY_TOMBSTONES_TABLE_NAME} \
+             (key_id, label, owner_peer_id, timestamp_issued, signature, timestamp_published) \
+             VALUES (?, ?, ?, ?, ?, ?);
+         "))?;
+
+        statement.bind(1, &Value::String(tombstone.tombstone.key_id))?;
+        statement.bind(2, &Value::String(tombstone.tombstone.label))?;
+        statement.bind(3, &Value::String(tombstone.tombstone.owner_peer_id))?;
+        statement.bind(
+            4,
+            &Value::Integer(tombstone.tombstone.timestamp_issued as i64),
+        )?;
+        statement.bind(5, &Value::Binary(tombstone.tombstone.signature))?;
+        statement.bind(6, &Value::Integer(tombstone.timestamp_published as i64))?;
+        statement.next()?;
+        Ok(())
+    }
+
+    /// Write key tombstone with the transfer chain it was verified with if there is no newer one
+    /// and remove the key with all its records and tombstones if it was created before the key tombstone.
+    /// The chain is kept along with the tombstone
+    pub fn update_key_tombstone(
+        &self,
+        tombstone: KeyTombstoneInternal,
+        transfers: Vec<KeyTransfer>,
+    ) -> Result<(), ServiceError> {
+        let key_id = tombstone.tombstone.key_id.clone();
+        if let Ok(existing) = self.get_key_tombstone(key_id.clone()) {
+            if existing.timestamp_issued > tombstone.tombstone.timestamp_issued {
+                return Err(NewerKeyTombstoneExists(key_id));
+            }
+        }
+
+        self.transaction(|| {
+            let timestamp_issued = tombstone.tombstone.timestamp_issued;
+            self.write_key_tombstone(tombstone)?;
+            if self.get_key_transfers(&key_id)? != transfers {
+                self.write_key_transfers(&key_id, transfers)?;
+            }
+
+            if let Ok(key) = self.get_key(key_id.clone()) {
+                if key.timestamp_created <= timestamp_issued {
+                    self.delete_key(key_id.clone())?;
+                    self.delete_records_by_key(&key_id)?;
+                    self.log_change(&key_id, CHANGE_KEY_REMOVED, "", "")?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Stale key tombstones with their transfer chains
+    pub fn get_stale_key_tombstones(
+        &self,
+        stale_timestamp: u64,
+    ) -> Result<Vec<(KeyTombstone, Vec<KeyTransfer>)>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT key_id, label, owner_peer_id, timestamp_issued, signature \
+                              FROM {KEY_TOMBSTONES_TABLE_NAME} WHERE timestamp_published <= ?"
+        ))?;
+        statement.bind(1, &Value::Integer(stale_timestamp as i64))?;
+
+        let mut stale_tombstones = vec![];
+        while let State::Row = statement.next()? {
+            let tombstone = read_key_tombstone(&statement)?;
+            let transfers = self.get_key_transfers(&tombstone.key_id)?;
+            stale_tombstones.push((tombstone, transfers));
+        }
+
+        Ok(stale_tombstones)
+    }
+
+    /// Remove key tombstones issued before or at `tombstone_expired_timestamp`, they are kept
+    /// for `tombstone_retention` like record tombstones, so the removed key can't be republished back
+    pub fn clear_expired_key_tombstones(
+        &self,
+        tombstone_expired_timestamp: u64,
+    ) -> Result<u64, ServiceError> {
+        self.connection.execute(f!(
+            "DELETE FROM {KEY_TOMBSTONES_TABLE_NAME} WHERE timestamp_issued <= {tombstone_expired_timestamp}"
+        ))?;
+        Ok(self.connection.changes() as u64)
+    }
+}
+
+pub fn read_key_tombstone(statement: &Statement) -> Result<KeyTombstone, ServiceError> {
+    Ok(KeyTombstone {
+        key_id: statement.read::<String>(0)?,
+        label: statement.read::<String>(1)?,
+        owner_peer_id: statement.read::<String>(2)?,
+        timestamp_issued: statement.read::<i64>(3)? as u64,
+        signature: statement.read::<Vec<u8>>(4)?,
+    })
+}
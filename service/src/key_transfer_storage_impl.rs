@@ -0,0 +1,198 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::defaults::{KEYS_TABLE_NAME, KEY_TOMBSTONES_TABLE_NAME, KEY_TRANSFERS_TABLE_NAME};
+use crate::error::ServiceError;
+use crate::key::{Key, KeyInternal};
+use crate::key_transfer::{get_effective_owner, verify_key_signature, KeyTransfer};
+use crate::storage_impl::Storage;
+use marine_sqlite_connector::{State, Statement, Value};
+use registry_client::ClientError::{KeyTransferNotByOwner, NewerKeyTransferExists};
+
+impl Storage {
+    /// Transfer chains of keys, they are kept while the key or its key tombstone is stored.
+    /// Transfers stored in the keys and key tombstones tables before are moved here
+    pub fn create_key_transfers_table(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE TABLE IF NOT EXISTS {KEY_TRANSFERS_TABLE_NAME} (
+                key_id TEXT,
+                issued_by TEXT,
+                new_owner_peer_id TEXT,
+                timestamp_issued INTEGER NOT NULL,
+                signature BLOB NOT NULL,
+                PRIMARY KEY (key_id, timestamp_issued)
+            );
+
+            INSERT OR IGNORE INTO {KEY_TRANSFERS_TABLE_NAME} \
+                (key_id, issued_by, new_owner_peer_id, timestamp_issued, signature) \
+                SELECT key_id, transfer_issued_by, transfer_new_owner_peer_id, transfer_timestamp_issued, transfer_signature \
+                FROM {KEYS_TABLE_NAME} WHERE transfer_issued_by IS NOT NULL;
+
+            INSERT OR IGNORE INTO {KEY_TRANSFERS_TABLE_NAME} \
+                (key_id, issued_by, new_owner_peer_id, timestamp_issued, signature) \
+                SELECT key_id, transfer_issued_by, transfer_new_owner_peer_id, transfer_timestamp_issued, transfer_signature \
+                FROM {KEY_TOMBSTONES_TABLE_NAME} WHERE transfer_issued_by IS NOT NULL;
+        "))?;
+
+        // key digests cover the whole chain now
+        self.clear_key_digests()
+    }
+
+    /// Transfer chain of the key ordered by `timestamp_issued`, empty if the key wasn't transferred
+    pub fn get_key_transfers(&self, key_id: &str) -> Result<Vec<KeyTransfer>, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT key_id, issued_by, new_owner_peer_id, timestamp_issued, signature \
+                              FROM {KEY_TRANSFERS_TABLE_NAME} WHERE key_id = ? ORDER BY timestamp_issued"
+        ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+
+        let mut transfers = vec![];
+        while let State::Row = statement.next()? {
+            transfers.push(read_key_transfer(&statement)?);
+        }
+
+        Ok(transfers)
+    }
+
+    /// The latest transfer of the stored key
+    pub fn get_key_transfer(&self, key_id: &str) -> Result<Option<KeyTransfer>, ServiceError> {
+        self.check_key_existence(key_id)?;
+        Ok(self.get_key_transfers(key_id)?.pop())
+    }
+
+    /// Replace the transfer chain of the key, the chain should be verified
+    pub fn write_key_transfers(
+        &self,
+        key_id: &str,
+        transfers: Vec<KeyTransfer>,
+    ) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "DELETE FROM {KEY_TRANSFERS_TABLE_NAME} WHERE key_id = ?"
+        ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.next()?;
+
+        for transfer in transfers {
+            let mut statement =
+                self.connection
+                    .prepare(f!("INSERT INTO {KEY_TRANSFERS_TABLE_NAME} \
+                 (key_id, issued_by, new_owner_peer_id, timestamp_issued, signature) \
+                 VALUES (?, ?, ?, ?, ?)"))?;
+            statement.bind(1, &Value::String(transfer.key_id))?;
+            statement.bind(2, &Value::String(transfer.issued_by))?;
+            statement.bind(3, &Value::String(transfer.new_owner_peer_id))?;
+            statement.bind(4, &Value::Integer(transfer.timestamp_issued as i64))?;
+            statement.bind(5, &Value::Binary(transfer.signature))?;
+            statement.next()?;
+        }
+
+        self.update_latest_key_transfer(key_id)
+    }
+
+    /// Copy the latest link of the chain to the keys table, it's used by the owner index
+    pub fn update_latest_key_transfer(&self, key_id: &str) -> Result<(), ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "UPDATE {KEYS_TABLE_NAME} SET \
+                (transfer_issued_by, transfer_new_owner_peer_id, transfer_timestamp_issued, transfer_signature) = \
+                (SELECT issued_by, new_owner_peer_id, timestamp_issued, signature \
+                 FROM {KEY_TRANSFERS_TABLE_NAME} WHERE key_id = ?1 ORDER BY timestamp_issued DESC LIMIT 1) \
+             WHERE key_id = ?1"
+        ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.next()?;
+        Ok(())
+    }
+
+    /// Append the transfer to the chain if it is issued by the current owner and newer than the latest one
+    pub fn update_key_transfer(&self, transfer: KeyTransfer) -> Result<(), ServiceError> {
+        let key = self.get_key(transfer.key_id.clone())?;
+        let mut transfers = self.get_key_transfers(&transfer.key_id)?;
+
+        if transfers.contains(&transfer) {
+            return Ok(());
+        }
+
+        if let Some(latest) = transfers.last() {
+            if latest.timestamp_issued >= transfer.timestamp_issued {
+                return Err(NewerKeyTransferExists(transfer.key_id).into());
+            }
+        }
+
+        let owner = get_effective_owner(&key, &transfers);
+        if transfer.issued_by != owner {
+            return Err(KeyTransferNotByOwner(transfer.key_id, transfer.issued_by, owner).into());
+        }
+
+        let key_id = transfer.key_id.clone();
+        transfers.push(transfer);
+        self.transaction(|| self.write_key_transfers(&key_id, transfers))
+    }
+
+    /// Current key owner taking into account its transfer chain
+    pub fn get_key_owner(&self, key: &Key) -> Result<String, ServiceError> {
+        Ok(get_effective_owner(key, &self.get_key_transfers(&key.id)?))
+    }
+
+    /// Verify the key signature by its owner at `timestamp_created` according
+    /// to the stored transfer chain, return peer id of the signer
+    pub fn verify_key_signature(&self, key: &Key, accept_v1: bool) -> Result<String, ServiceError> {
+        Ok(verify_key_signature(
+            key,
+            &self.get_key_transfers(&key.id)?,
+            accept_v1,
+        )?)
+    }
+
+    /// Update the key and its transfer chain at once, used for replication of transferred keys.
+    /// `transfers` should be verified and merged with the stored chain
+    pub fn update_key_with_transfers(
+        &self,
+        key: KeyInternal,
+        transfers: Vec<KeyTransfer>,
+    ) -> Result<(), ServiceError> {
+        let key_id = key.key.id.clone();
+        self.transaction(|| {
+            match self.update_key(key) {
+                Err(ServiceError::KeyAlreadyExistsNewerTimestamp(_, _)) => {}
+                other => other?,
+            }
+
+            if self.get_key_transfers(&key_id)? != transfers {
+                self.write_key_transfers(&key_id, transfers)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Remove chains of keys removed along with their key tombstones
+    pub fn clear_orphan_key_transfers(&self) -> Result<(), ServiceError> {
+        self.connection
+            .execute(f!("DELETE FROM {KEY_TRANSFERS_TABLE_NAME} \
+             WHERE key_id NOT IN (SELECT key_id FROM {KEYS_TABLE_NAME}) \
+             AND key_id NOT IN (SELECT key_id FROM {KEY_TOMBSTONES_TABLE_NAME})"))?;
+        Ok(())
+    }
+}
+
+pub fn read_key_transfer(statement: &Statement) -> Result<KeyTransfer, ServiceError> {
+    Ok(KeyTransfer {
+        key_id: statement.read::<String>(0)?,
+        issued_by: statement.read::<String>(1)?,
+        new_owner_peer_id: statement.read::<String>(2)?,
+        timestamp_issued: statement.read::<i64>(3)? as u64,
+        signature: statement.read::<Vec<u8>>(4)?,
+    })
+}
@@ -18,18 +18,33 @@
 use marine_rs_sdk::marine;
 use marine_rs_sdk::module_manifest;
 
-use crate::config::{create_config, load_config, write_config};
-use crate::results::{ClearExpiredResult, EvictStaleResult};
+use crate::config::{check_host, create_config, load_config, update_config};
+use crate::results::{ClearExpiredResult, EvictStaleResult, GetConfigResult, RegistryResult};
 use crate::storage_impl::get_storage;
 use crate::tetraplets_checkers::check_timestamp_tetraplets;
 
+mod change;
+mod change_api;
+mod change_storage_impl;
 mod config;
 mod defaults;
+mod digest;
+mod digest_api;
+mod digest_storage_impl;
 mod error;
+mod expiry_policy;
 mod key;
 mod key_api;
 mod key_storage_impl;
+mod key_tombstone;
+mod key_tombstone_api;
+mod key_tombstone_storage_impl;
+mod key_transfer;
+mod key_transfer_storage_impl;
+mod migrations;
 mod misc;
+mod permissions;
+mod quota_storage_impl;
 mod record;
 mod record_api;
 mod record_storage_impl;
@@ -64,9 +79,12 @@ pub struct WeightResult {
 
 fn main() {
     let storage = get_storage().unwrap();
-    storage.create_key_tables();
-    storage.create_records_table();
-    create_config();
+    if let Err(error) = storage.migrate() {
+        panic!("registry storage migration failed: {}", error);
+    }
+    if let Err(error) = create_config() {
+        panic!("registry config creation failed: {}", error);
+    }
 }
 
 #[marine]
@@ -89,16 +107,147 @@ pub fn evict_stale(current_timestamp_sec: u64) -> EvictStaleResult {
     .into()
 }
 
+/// Effective config with defaults for missing fields
 #[marine]
-pub fn set_expired_timeout(timeout_sec: u64) {
-    let mut config = load_config();
-    config.expired_timeout = timeout_sec;
-    write_config(config);
+pub fn get_config() -> GetConfigResult {
+    wrapped_try(load_config).into()
 }
 
+/// Can be called only by the host or an admin
 #[marine]
-pub fn set_stale_timeout(timeout_sec: u64) {
-    let mut config = load_config();
-    config.stale_timeout = timeout_sec;
-    write_config(config);
+pub fn set_expired_timeout(timeout_sec: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| {
+            config.expired_timeout = timeout_sec
+        })
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin
+#[marine]
+pub fn set_stale_timeout(timeout_sec: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| {
+            config.stale_timeout = timeout_sec
+        })
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin
+#[marine]
+pub fn set_max_record_ttl(ttl_sec: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| config.max_record_ttl = ttl_sec)
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin
+#[marine]
+pub fn set_tombstone_retention(retention_sec: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| {
+            config.tombstone_retention = retention_sec
+        })
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin
+#[marine]
+pub fn set_records_limit(limit: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| config.records_limit = limit)
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin
+#[marine]
+pub fn set_max_key_records_limit(limit: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| {
+            config.max_key_records_limit = limit
+        })
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin, `policy` is weight, recency or issuer_diversity
+#[marine]
+pub fn set_eviction_policy(policy: String) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| config.eviction_policy = policy)
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin
+#[marine]
+pub fn set_quota_window(window_sec: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| config.quota_window = window_sec)
+    })
+    .into()
+}
+
+/// Can be called only by the host or an admin
+#[marine]
+pub fn set_write_quotas(key_writes: u64, record_writes: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| {
+            config.key_writes_quota = key_writes;
+            config.record_writes_quota = record_writes;
+        })
+    })
+    .into()
+}
+
+/// Initiators with weight at least `trusted_weight` get quotas multiplied by `multiplier`,
+/// can be called only by the host or an admin
+#[marine]
+pub fn set_trusted_quota(trusted_weight: u32, multiplier: u64) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| {
+            config.trusted_weight = trusted_weight;
+            config.trusted_quota_multiplier = multiplier;
+        })
+    })
+    .into()
+}
+
+/// Disable to reject signatures of the legacy v1 payloads once all peers sign v2,
+/// can be called only by the host or an admin
+#[marine]
+pub fn set_accept_v1_signatures(accept: bool) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        update_config(&call_parameters, |config| {
+            config.accept_v1_signatures = accept
+        })
+    })
+    .into()
+}
+
+/// Replace the list of peers allowed to change the config, can be called only by the host
+#[marine]
+pub fn set_admins(admins: Vec<String>) -> RegistryResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_host(&call_parameters)?;
+        update_config(&call_parameters, |config| config.admins = admins)
+    })
+    .into()
 }
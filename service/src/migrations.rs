@@ -0,0 +1,167 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::defaults::SCHEMA_VERSION_TABLE_NAME;
+use crate::error::ServiceError;
+use crate::error::ServiceError::{InternalError, MigrationFailed};
+use crate::storage_impl::Storage;
+use marine_sqlite_connector::{State, Value};
+
+pub struct Migration {
+    /// schema version after the migration is applied
+    pub version: u64,
+    pub description: &'static str,
+    pub apply: fn(&Storage) -> Result<(), ServiceError>,
+}
+
+/// All schema migrations in the order of application, versions must be strictly increasing.
+/// Released migrations must never be changed, add a new one instead.
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create keys and records tables",
+        apply: initial_schema,
+    },
+    Migration {
+        version: 2,
+        description: "create key tombstones table",
+        apply: Storage::create_key_tombstones_table,
+    },
+    Migration {
+        version: 3,
+        description: "add key transfer columns to keys table",
+        apply: Storage::add_key_transfer_columns,
+    },
+    Migration {
+        version: 4,
+        description: "create keys owner index",
+        apply: Storage::create_key_owner_index,
+    },
+    Migration {
+        version: 5,
+        description: "create keys label index",
+        apply: Storage::create_key_label_index,
+    },
+    Migration {
+        version: 6,
+        description: "create records lookup indexes",
+        apply: Storage::create_records_lookup_indexes,
+    },
+    Migration {
+        version: 7,
+        description: "create changes table",
+        apply: Storage::create_changes_table,
+    },
+    Migration {
+        version: 8,
+        description: "add ttl column to records table",
+        apply: Storage::add_records_ttl_column,
+    },
+    Migration {
+        version: 9,
+        description: "add records limit column to keys table",
+        apply: Storage::add_key_records_limit_column,
+    },
+    Migration {
+        version: 10,
+        description: "create quotas table",
+        apply: Storage::create_quotas_table,
+    },
+    Migration {
+        version: 11,
+        description: "add key transfer columns to key tombstones table",
+        apply: Storage::add_key_tombstone_transfer_columns,
+    },
+    Migration {
+        version: 12,
+        description: "add digest columns and triggers to keys table",
+        apply: Storage::add_key_digest_columns,
+    },
+    Migration {
+        version: 13,
+        description: "create key transfers table",
+        apply: Storage::create_key_transfers_table,
+    },
+];
+
+fn initial_schema(storage: &Storage) -> Result<(), ServiceError> {
+    storage.create_key_tables()?;
+    storage.create_records_table()
+}
+
+impl Storage {
+    fn create_schema_version_table(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE TABLE IF NOT EXISTS {SCHEMA_VERSION_TABLE_NAME} (
+                version INTEGER PRIMARY KEY,
+                description TEXT
+            );
+        "))?;
+        Ok(())
+    }
+
+    /// Version of the latest applied migration, 0 for a fresh or pre-migrations database
+    pub fn get_schema_version(&self) -> Result<u64, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT COALESCE(MAX(version), 0) FROM {SCHEMA_VERSION_TABLE_NAME}"
+        ))?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<i64>(0)? as u64)
+        } else {
+            Err(InternalError(
+                "MAX should always return something".to_string(),
+            ))
+        }
+    }
+
+    fn write_schema_version(&self, migration: &Migration) -> Result<(), ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("INSERT INTO {SCHEMA_VERSION_TABLE_NAME} VALUES (?, ?);"))?;
+        statement.bind(1, &Value::Integer(migration.version as i64))?;
+        statement.bind(2, &Value::String(migration.description.to_string()))?;
+        statement.next().map(drop)?;
+
+        Ok(())
+    }
+
+    /// Apply all pending migrations one by one, each in its own transaction.
+    /// Returns the resulting schema version.
+    pub fn migrate(&self) -> Result<u64, ServiceError> {
+        self.create_schema_version_table()?;
+
+        let current_version = self.get_schema_version()?;
+        let mut version = current_version;
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current_version) {
+            self.transaction(|| {
+                (migration.apply)(self)?;
+                self.write_schema_version(migration)
+            })
+            .map_err(|e| {
+                MigrationFailed(
+                    migration.version,
+                    migration.description.to_string(),
+                    e.to_string(),
+                )
+            })?;
+
+            version = migration.version;
+        }
+
+        Ok(version)
+    }
+}
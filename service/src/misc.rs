@@ -17,21 +17,14 @@
 use crate::error::ServiceError;
 use crate::WeightResult;
 use boolinator::Boolinator;
-use fluence_keypair::PublicKey;
-use libp2p_identity::PeerId;
-use std::convert::TryFrom;
-use std::str::FromStr;
 
-fn parse_peer_id(peer_id: String) -> Result<PeerId, ServiceError> {
-    PeerId::from_str(&peer_id).map_err(|e| ServiceError::PeerIdParseError(format!("{:?}", e)))
-}
-
-pub fn extract_public_key(peer_id: String) -> Result<PublicKey, ServiceError> {
-    PublicKey::try_from(
-        parse_peer_id(peer_id)
-            .map_err(|e| ServiceError::PublicKeyExtractionError(e.to_string()))?,
-    )
-    .map_err(ServiceError::PublicKeyDecodeError)
+/// Weight of the particle initiator if `weight` is obtained for it, 0 otherwise
+pub fn get_initiator_weight(init_peer_id: &str, weight: &WeightResult) -> u32 {
+    if weight.success && weight.peer_id == init_peer_id {
+        weight.weight
+    } else {
+        0
+    }
 }
 
 pub fn check_weight_result(peer_id: &str, weight: &WeightResult) -> Result<(), ServiceError> {
@@ -0,0 +1,171 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use crate::defaults::{
+    CHALLENGE_TYPE_ALLOWLIST, CHALLENGE_TYPE_DELEGATION, CHALLENGE_TYPE_OPEN, CHALLENGE_TYPE_OWNER,
+};
+use crate::error::ServiceError;
+use crate::error::ServiceError::{PermissionDenied, UnknownChallengeType};
+use crate::key::Key;
+use registry_client::extract_public_key;
+use registry_client::signing::{verify_v2_signature, CanonicalEncoder, DOMAIN_DELEGATION};
+
+/// Decides whether `issued_by` may publish records and tombstones for the key,
+/// based on `Key.challenge` and `RecordMetadata.solution` or `Tombstone.solution`.
+/// `owner_peer_id` is the current key owner, it differs from `Key.owner_peer_id` after key transfer
+pub trait ChallengeVerifier {
+    fn verify(
+        &self,
+        key: &Key,
+        owner_peer_id: &str,
+        issued_by: &str,
+        solution: &[u8],
+    ) -> Result<(), String>;
+}
+
+/// Anyone can publish
+pub struct OpenChallenge;
+
+/// Only the key owner can publish
+pub struct OwnerChallenge;
+
+/// Only the key owner and peers from the comma-separated list in `challenge` can publish
+pub struct AllowlistChallenge;
+
+/// The key owner and peers with `solution` containing the owner signature
+/// of `delegation_bytes(key_id, issued_by)` can publish
+pub struct DelegationChallenge;
+
+impl ChallengeVerifier for OpenChallenge {
+    fn verify(
+        &self,
+        _key: &Key,
+        _owner_peer_id: &str,
+        _issued_by: &str,
+        _solution: &[u8],
+    ) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl ChallengeVerifier for OwnerChallenge {
+    fn verify(
+        &self,
+        _key: &Key,
+        owner_peer_id: &str,
+        issued_by: &str,
+        _solution: &[u8],
+    ) -> Result<(), String> {
+        if owner_peer_id == issued_by {
+            Ok(())
+        } else {
+            Err("only the key owner is allowed".to_string())
+        }
+    }
+}
+
+impl AllowlistChallenge {
+    pub fn parse(challenge: &[u8]) -> Result<Vec<String>, String> {
+        let allowlist = std::str::from_utf8(challenge)
+            .map_err(|e| f!("challenge is not a comma-separated list of peer ids: {e}"))?;
+
+        Ok(allowlist
+            .split(',')
+            .map(|peer_id| peer_id.trim().to_string())
+            .filter(|peer_id| !peer_id.is_empty())
+            .collect())
+    }
+}
+
+impl ChallengeVerifier for AllowlistChallenge {
+    fn verify(
+        &self,
+        key: &Key,
+        owner_peer_id: &str,
+        issued_by: &str,
+        _solution: &[u8],
+    ) -> Result<(), String> {
+        if owner_peer_id == issued_by || Self::parse(&key.challenge)?.iter().any(|p| p == issued_by)
+        {
+            Ok(())
+        } else {
+            Err("peer is not in the allowlist".to_string())
+        }
+    }
+}
+
+impl ChallengeVerifier for DelegationChallenge {
+    fn verify(
+        &self,
+        key: &Key,
+        owner_peer_id: &str,
+        issued_by: &str,
+        solution: &[u8],
+    ) -> Result<(), String> {
+        if owner_peer_id == issued_by {
+            return Ok(());
+        }
+
+        let pk = extract_public_key(owner_peer_id.to_string()).map_err(|e| e.to_string())?;
+        let bytes = delegation_bytes(&key.id, issued_by);
+        verify_v2_signature(&pk, solution, &bytes)
+            .map_err(|e| f!("solution is not a valid delegation signed by the key owner: {e}"))
+    }
+}
+
+/// Bytes the key owner signs to delegate publishing to `issued_by`
+pub fn delegation_bytes(key_id: &str, issued_by: &str) -> Vec<u8> {
+    CanonicalEncoder::new(DOMAIN_DELEGATION)
+        .string(key_id)
+        .string(issued_by)
+        .finish()
+}
+
+/// Empty challenge type is treated as open for backward compatibility
+pub fn get_challenge_verifier(
+    challenge_type: &str,
+) -> Result<Box<dyn ChallengeVerifier>, ServiceError> {
+    match challenge_type {
+        "" | CHALLENGE_TYPE_OPEN => Ok(Box::new(OpenChallenge)),
+        CHALLENGE_TYPE_OWNER => Ok(Box::new(OwnerChallenge)),
+        CHALLENGE_TYPE_ALLOWLIST => Ok(Box::new(AllowlistChallenge)),
+        CHALLENGE_TYPE_DELEGATION => Ok(Box::new(DelegationChallenge)),
+        other => Err(UnknownChallengeType(other.to_string())),
+    }
+}
+
+/// Check the key challenge is well-formed before the key is stored
+pub fn check_challenge(key: &Key) -> Result<(), ServiceError> {
+    get_challenge_verifier(&key.challenge_type)?;
+    if key.challenge_type == CHALLENGE_TYPE_ALLOWLIST {
+        AllowlistChallenge::parse(&key.challenge)
+            .map_err(|e| ServiceError::InvalidChallenge(key.id.clone(), e))?;
+    }
+
+    Ok(())
+}
+
+/// Check `issued_by` is permitted to publish records or tombstones for the key
+pub fn check_permissions(
+    key: &Key,
+    owner_peer_id: &str,
+    issued_by: &str,
+    solution: &[u8],
+) -> Result<(), ServiceError> {
+    get_challenge_verifier(&key.challenge_type)?
+        .verify(key, owner_peer_id, issued_by, solution)
+        .map_err(|reason| PermissionDenied(key.id.clone(), issued_by.to_string(), reason))
+}
@@ -0,0 +1,112 @@
+/*
+ * Copyright 2021 Fluence Labs Limited
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+use crate::config::{load_config, Config};
+use crate::defaults::QUOTAS_TABLE_NAME;
+use crate::error::ServiceError;
+use crate::error::ServiceError::QuotaExceeded;
+use crate::storage_impl::Storage;
+use marine_rs_sdk::CallParameters;
+use marine_sqlite_connector::{State, Value};
+
+impl Storage {
+    pub fn create_quotas_table(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE TABLE IF NOT EXISTS {QUOTAS_TABLE_NAME} (
+                peer_id TEXT,
+                kind TEXT,
+                window_start INTEGER NOT NULL,
+                writes INTEGER NOT NULL,
+                PRIMARY KEY (peer_id, kind)
+            );
+        "))?;
+        Ok(())
+    }
+
+    /// Writes of `kind` made by `peer_id` in the window started at `window_start`
+    pub fn get_quota_writes(
+        &self,
+        peer_id: &str,
+        kind: &str,
+        window_start: u64,
+    ) -> Result<u64, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("SELECT writes FROM {QUOTAS_TABLE_NAME} \
+             WHERE peer_id = ? AND kind = ? AND window_start = ?"))?;
+        statement.bind(1, &Value::String(peer_id.to_string()))?;
+        statement.bind(2, &Value::String(kind.to_string()))?;
+        statement.bind(3, &Value::Integer(window_start as i64))?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<i64>(0)? as u64)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Count `count` writes of `kind` by the particle initiator in the current window,
+    /// return an error if its quota is exceeded. The host is not limited
+    pub fn consume_write_quota(
+        &self,
+        config: &Config,
+        call_parameters: &CallParameters,
+        kind: &str,
+        count: u64,
+        weight: u32,
+        current_timestamp_sec: u64,
+    ) -> Result<(), ServiceError> {
+        let peer_id = &call_parameters.init_peer_id;
+        if *peer_id == call_parameters.host_id || count == 0 {
+            return Ok(());
+        }
+
+        let quota = config.get_write_quota(kind, weight);
+        let window_start = config.get_quota_window_start(current_timestamp_sec);
+        let writes = self.get_quota_writes(peer_id, kind, window_start)? + count;
+        if writes > quota {
+            return Err(QuotaExceeded(
+                peer_id.clone(),
+                kind.to_string(),
+                quota,
+                config.quota_window,
+            ));
+        }
+
+        let mut statement = self.connection.prepare(f!(
+            "INSERT OR REPLACE INTO {QUOTAS_TABLE_NAME} (peer_id, kind, window_start, writes) \
+             VALUES (?, ?, ?, ?);"
+        ))?;
+        statement.bind(1, &Value::String(peer_id.clone()))?;
+        statement.bind(2, &Value::String(kind.to_string()))?;
+        statement.bind(3, &Value::Integer(window_start as i64))?;
+        statement.bind(4, &Value::Integer(writes as i64))?;
+        statement.next().map(drop)?;
+
+        Ok(())
+    }
+
+    /// Remove counters of windows finished before the current one
+    pub fn clear_expired_quotas(&self, current_timestamp_sec: u64) -> Result<u64, ServiceError> {
+        let window_start = load_config()?.get_quota_window_start(current_timestamp_sec);
+        let mut statement = self
+            .connection
+            .prepare(f!("DELETE FROM {QUOTAS_TABLE_NAME} WHERE window_start < ?"))?;
+        statement.bind(1, &Value::Integer(window_start as i64))?;
+        statement.next().map(drop)?;
+
+        Ok(self.connection.changes() as u64)
+    }
+}
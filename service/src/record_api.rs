@@ -13,16 +13,22 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use crate::config::{load_config, Config};
+use crate::defaults::QUOTA_KIND_RECORD_WRITES;
 use crate::error::ServiceError;
 use crate::error::ServiceError::MissingRecordWeight;
-use crate::misc::check_weight_result;
-use crate::record::{Record, RecordInternal, RecordMetadata};
+use crate::expiry_policy::ExpiryPolicy;
+use crate::misc::{check_weight_result, get_initiator_weight};
+use crate::permissions::check_permissions;
+use crate::record::{verify_record, Record, RecordInternal, RecordMetadata};
 use crate::record_storage_impl::merge_records;
-use crate::results::{GetRecordsResult, MergeResult, RegistryResult, RepublishRecordsResult};
-use crate::storage_impl::get_storage;
+use crate::results::{
+    GetRecordsResult, MergeResult, PutRecordsResult, RegistryResult, RepublishRecordsResult,
+};
+use crate::storage_impl::{get_storage, Storage};
 use crate::tetraplets_checkers::{check_timestamp_tetraplets, check_weight_tetraplets};
-use crate::{load_config, wrapped_try, WeightResult};
-use marine_rs_sdk::marine;
+use crate::{wrapped_try, WeightResult};
+use marine_rs_sdk::{marine, CallParameters};
 
 #[marine]
 pub fn get_record_metadata_bytes(
@@ -34,6 +40,7 @@ pub fn get_record_metadata_bytes(
     relay_id: Vec<String>,
     service_id: Vec<String>,
     solution: Vec<u8>,
+    ttl: Vec<u64>,
 ) -> Vec<u8> {
     RecordMetadata {
         key_id,
@@ -44,12 +51,14 @@ pub fn get_record_metadata_bytes(
         relay_id,
         service_id,
         solution,
+        ttl,
         ..Default::default()
     }
     .signature_bytes()
 }
 
 #[marine]
+#[allow(clippy::too_many_arguments)]
 pub fn create_record_metadata(
     key_id: String,
     issued_by: String,
@@ -59,6 +68,7 @@ pub fn create_record_metadata(
     relay_id: Vec<String>,
     service_id: Vec<String>,
     solution: Vec<u8>,
+    ttl: Vec<u64>,
     signature: Vec<u8>,
 ) -> RecordMetadata {
     RecordMetadata {
@@ -70,6 +80,7 @@ pub fn create_record_metadata(
         relay_id,
         service_id,
         solution,
+        ttl,
         issuer_signature: signature,
     }
 }
@@ -97,18 +108,141 @@ pub fn put_record(
         check_weight_tetraplets(&cp, 3, 0)?;
         check_timestamp_tetraplets(&cp, 4)?;
         check_weight_result(&cp.init_peer_id, &weight)?;
+        let config = load_config()?;
+        let storage = get_storage()?;
         let record = Record {
             metadata,
             timestamp_created,
             signature,
         };
-        record.verify(current_timestamp_sec)?;
+        // the write is counted against the quota only if the record is stored
+        storage.transaction(|| {
+            storage.consume_write_quota(
+                &config,
+                &cp,
+                QUOTA_KIND_RECORD_WRITES,
+                1,
+                get_initiator_weight(&cp.init_peer_id, &weight),
+                current_timestamp_sec,
+            )?;
+            verify_record(
+                &record,
+                current_timestamp_sec,
+                &ExpiryPolicy::from(&config),
+                config.accept_v1_signatures,
+            )?;
+
+            let key = storage.get_key(record.metadata.key_id.clone())?;
+            let owner_peer_id = storage.get_key_owner(&key)?;
+            check_permissions(
+                &key,
+                &owner_peer_id,
+                &record.metadata.issued_by,
+                &record.metadata.solution,
+            )?;
+            storage.update_record(RecordInternal {
+                record,
+                weight: weight.weight,
+            })
+        })
+    })
+    .into()
+}
+
+fn check_and_put_record(
+    storage: &Storage,
+    call_parameters: &CallParameters,
+    index: usize,
+    record: Record,
+    weight: Option<&WeightResult>,
+    current_timestamp_sec: u64,
+    config: &Config,
+) -> Result<(), ServiceError> {
+    check_weight_tetraplets(call_parameters, 1, index)?;
+    let weight = weight.ok_or_else(|| {
+        MissingRecordWeight(
+            record.metadata.peer_id.clone(),
+            record.metadata.issued_by.clone(),
+        )
+    })?;
+    check_weight_result(&record.metadata.issued_by, weight)?;
+    verify_record(
+        &record,
+        current_timestamp_sec,
+        &ExpiryPolicy::from(config),
+        config.accept_v1_signatures,
+    )?;
+
+    let key = storage.get_key(record.metadata.key_id.clone())?;
+    let owner_peer_id = storage.get_key_owner(&key)?;
+    check_permissions(
+        &key,
+        &owner_peer_id,
+        &record.metadata.issued_by,
+        &record.metadata.solution,
+    )?;
+    storage.update_record(RecordInternal {
+        record,
+        weight: weight.weight,
+    })
+}
+
+/// Put records for any number of keys at once, `weights[i]` is the weight of `records[i].metadata.issued_by`.
+/// All records are written in a single transaction, failed records are skipped
+/// and reported in the per-record results in the same order.
+/// Stored records count against the initiator's quota, relaxed if the initiator is one of the issuers,
+/// the whole batch fails if they exceed it
+#[marine]
+pub fn put_records(
+    records: Vec<Record>,
+    weights: Vec<WeightResult>,
+    current_timestamp_sec: u64,
+) -> PutRecordsResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 2)?;
 
         let storage = get_storage()?;
-        storage.check_key_existence(&record.metadata.key_id)?;
-        storage.update_record(RecordInternal {
-            record,
-            weight: weight.weight,
+        let initiator_weight = weights
+            .iter()
+            .enumerate()
+            .find(|(i, weight)| {
+                weight.peer_id == call_parameters.init_peer_id
+                    && check_weight_tetraplets(&call_parameters, 1, *i).is_ok()
+            })
+            .map_or(0, |(_, weight)| {
+                get_initiator_weight(&call_parameters.init_peer_id, weight)
+            });
+        let config = load_config()?;
+        storage.transaction(|| {
+            let results: Vec<RegistryResult> = records
+                .into_iter()
+                .enumerate()
+                .map(|(i, record)| {
+                    storage
+                        .transaction(|| {
+                            check_and_put_record(
+                                &storage,
+                                &call_parameters,
+                                i,
+                                record,
+                                weights.get(i),
+                                current_timestamp_sec,
+                                &config,
+                            )
+                        })
+                        .into()
+                })
+                .collect();
+            storage.consume_write_quota(
+                &config,
+                &call_parameters,
+                QUOTA_KIND_RECORD_WRITES,
+                results.iter().filter(|r| r.success).count() as u64,
+                initiator_weight,
+                current_timestamp_sec,
+            )?;
+            Ok(results)
         })
     })
     .into()
@@ -129,6 +263,48 @@ pub fn get_records(key_id: String, current_timestamp_sec: u64) -> GetRecordsResu
     .into()
 }
 
+/// Return records of all keys advertising the service
+#[marine]
+pub fn get_records_by_service_id(
+    service_id: String,
+    current_timestamp_sec: u64,
+) -> GetRecordsResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 1)?;
+        get_storage()?
+            .get_records_by_service_id(service_id, current_timestamp_sec)
+            .map(|records| records.into_iter().map(|r| r.record).collect())
+    })
+    .into()
+}
+
+/// Return records of all keys hosted by the peer
+#[marine]
+pub fn get_records_by_peer_id(peer_id: String, current_timestamp_sec: u64) -> GetRecordsResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 1)?;
+        get_storage()?
+            .get_records_by_peer_id(peer_id, current_timestamp_sec)
+            .map(|records| records.into_iter().map(|r| r.record).collect())
+    })
+    .into()
+}
+
+/// Return records of all keys issued by the peer
+#[marine]
+pub fn get_records_by_issuer(issued_by: String, current_timestamp_sec: u64) -> GetRecordsResult {
+    wrapped_try(|| {
+        let call_parameters = marine_rs_sdk::get_call_parameters();
+        check_timestamp_tetraplets(&call_parameters, 1)?;
+        get_storage()?
+            .get_records_by_issuer(issued_by, current_timestamp_sec)
+            .map(|records| records.into_iter().map(|r| r.record).collect())
+    })
+    .into()
+}
+
 /// Return all values by key
 #[marine]
 pub fn get_stale_local_records(current_timestamp_sec: u64) -> GetRecordsResult {
@@ -138,9 +314,8 @@ pub fn get_stale_local_records(current_timestamp_sec: u64) -> GetRecordsResult {
         let storage = get_storage()?;
 
         // TODO: add some meaningful constant for expiring local records
-        let stale_timestamp_sec = current_timestamp_sec - load_config().expired_timeout + 100;
         storage
-            .get_local_stale_records(stale_timestamp_sec)
+            .get_local_stale_records(current_timestamp_sec + 100)
             .map(|records| records.into_iter().map(|r| r.record).collect())
     })
     .into()
@@ -161,10 +336,13 @@ pub fn republish_records(
         let key_id = records[0].metadata.key_id.clone();
         let call_parameters = marine_rs_sdk::get_call_parameters();
         check_timestamp_tetraplets(&call_parameters, 2)?;
+        let config = load_config()?;
+        let expiry_policy = ExpiryPolicy::from(&config);
+        let accept_v1 = config.accept_v1_signatures;
         let mut records_to_merge = vec![];
 
         for (i, record) in records.into_iter().enumerate() {
-            record.verify(current_timestamp_sec)?;
+            verify_record(&record, current_timestamp_sec, &expiry_policy, accept_v1)?;
             check_weight_tetraplets(&call_parameters, 1, i)?;
             let weight_result = weights.get(i).ok_or_else(|| {
                 MissingRecordWeight(
@@ -184,7 +362,16 @@ pub fn republish_records(
         }
 
         let storage = get_storage()?;
-        storage.check_key_existence(&key_id)?;
+        let key = storage.get_key(key_id.clone())?;
+        let owner_peer_id = storage.get_key_owner(&key)?;
+        for record in records_to_merge.iter() {
+            check_permissions(
+                &key,
+                &owner_peer_id,
+                &record.record.metadata.issued_by,
+                &record.record.metadata.solution,
+            )?;
+        }
         storage.merge_and_update_records(key_id, records_to_merge, current_timestamp_sec)
     })
     .into()
@@ -14,18 +14,26 @@
  * limitations under the License.
  */
 
-use std::collections::HashMap;
-
-use crate::defaults::{RECORDS_LIMIT, RECORDS_TABLE_NAME};
+use std::collections::BTreeMap;
+
+use crate::config::load_config;
+use crate::defaults::{
+    CHANGE_EXPIRED, CHANGE_RECORD_EVICTED, CHANGE_RECORD_UPSERTED,
+    EVICTION_POLICY_ISSUER_DIVERSITY, EVICTION_POLICY_RECENCY, EVICTION_POLICY_WEIGHT,
+    RECORDS_TABLE_NAME,
+};
 use crate::error::ServiceError;
-use crate::error::ServiceError::InternalError;
-use crate::load_config;
+use crate::error::ServiceError::{InternalError, UnknownEvictionPolicy};
+use crate::expiry_policy::{load_expiry_policy, ExpiryPolicy};
 use crate::record::{Record, RecordInternal, RecordMetadata};
-use crate::storage_impl::{from_custom_option, get_custom_option, Storage};
+use crate::storage_impl::Storage;
 use marine_sqlite_connector::{State, Statement, Value};
+use registry_client::misc::{from_custom_option, get_custom_option};
 
 impl Storage {
-    pub fn create_records_table(&self) {
+    /// Create records table on a fresh database. The table of a pre-migrations database is kept
+    /// only if it has exactly the same schema, otherwise the error is returned.
+    pub fn create_records_table(&self) -> Result<(), ServiceError> {
         let table_schema = f!("
             CREATE TABLE IF NOT EXISTS {RECORDS_TABLE_NAME} (
                 key_id TEXT,
@@ -44,20 +52,9 @@ impl Storage {
                 PRIMARY KEY (key_id, issued_by, peer_id)
             );
         ");
-
-        let current_table_schema = self
-            .get_table_schema(RECORDS_TABLE_NAME.to_string())
-            .expect(f!("failed to get {RECORDS_TABLE_NAME} table schema").as_str());
-        if !current_table_schema.is_empty() && table_schema != current_table_schema {
-            self.delete_table(RECORDS_TABLE_NAME.to_string())
-                .expect(f!("failed to delete {RECORDS_TABLE_NAME} table").as_str())
-        }
-
-        let result = self.connection.execute(table_schema);
-
-        if let Err(error) = result {
-            println!("create_records_table error: {}", error);
-        }
+        self.check_table_schema(RECORDS_TABLE_NAME, &table_schema)?;
+        self.connection.execute(table_schema)?;
+        Ok(())
     }
 
     pub fn update_record(&self, record: RecordInternal) -> Result<(), ServiceError> {
@@ -67,33 +64,83 @@ impl Storage {
         if record.record.metadata.peer_id == host_id {
             self.write_record(record)
         } else {
-            let records_count =
-                self.get_non_host_records_count_by_key(&record.record.metadata.key_id)?;
-            // check values limits for non-host values
-            if records_count >= RECORDS_LIMIT {
-                let min_weight_record =
-                    self.get_min_weight_non_host_record_by_key(&record.record.metadata.key_id)?;
-
-                if min_weight_record.weight < record.weight
-                    || (min_weight_record.weight == record.weight
-                        && min_weight_record.record.timestamp_created
-                            < record.record.timestamp_created)
-                {
-                    // delete the lightest record if the new one is heavier or newer
-                    self.delete_record(
-                        min_weight_record.record.metadata.key_id,
-                        min_weight_record.record.metadata.peer_id,
-                        min_weight_record.record.metadata.issued_by,
-                    )?;
-                } else {
-                    // return error if limit is exceeded
-                    return Err(ServiceError::ValuesLimitExceeded(
-                        record.record.metadata.key_id,
-                    ));
-                }
+            // eviction and insertion are applied together or not at all
+            self.transaction(|| self.update_non_host_record(record))
+        }
+    }
+
+    fn update_non_host_record(&self, record: RecordInternal) -> Result<(), ServiceError> {
+        let key_id = &record.record.metadata.key_id;
+        // an update of the existing record doesn't change the number of records
+        if self.is_record_stored(
+            key_id,
+            &record.record.metadata.issued_by,
+            &record.record.metadata.peer_id,
+        )? {
+            return self.write_record(record);
+        }
+
+        let config = load_config()?;
+        let limit = config.get_records_limit(self.get_key_records_limit(key_id)?) as usize;
+        let mut records_count = self.get_non_host_records_count_by_key(key_id)?;
+        // check values limits for non-host values, the limit can be lowered,
+        // so several records may be evicted
+        while records_count >= limit {
+            let candidate = self.get_eviction_candidate(key_id, &config.eviction_policy)?;
+            if !self.should_evict(&candidate, &record, &config.eviction_policy)? {
+                // return error if limit is exceeded
+                return Err(ServiceError::ValuesLimitExceeded(key_id.clone()));
             }
 
-            self.write_record(record)
+            let metadata = candidate.record.metadata;
+            self.delete_record(
+                metadata.key_id.clone(),
+                metadata.peer_id.clone(),
+                metadata.issued_by.clone(),
+            )?;
+            self.log_change(
+                &metadata.key_id,
+                CHANGE_RECORD_EVICTED,
+                &metadata.issued_by,
+                &metadata.peer_id,
+            )?;
+            records_count -= 1;
+        }
+
+        self.write_record(record)
+    }
+
+    /// Whether `candidate` should be evicted in favour of the new `record` according to `policy`
+    fn should_evict(
+        &self,
+        candidate: &RecordInternal,
+        record: &RecordInternal,
+        policy: &str,
+    ) -> Result<bool, ServiceError> {
+        // the lightest record is evicted if the new one is heavier or newer
+        let is_lighter_or_older = candidate.weight < record.weight
+            || (candidate.weight == record.weight
+                && candidate.record.timestamp_created < record.record.timestamp_created);
+
+        match policy {
+            EVICTION_POLICY_WEIGHT => Ok(is_lighter_or_older),
+            EVICTION_POLICY_RECENCY => {
+                Ok(candidate.record.timestamp_created < record.record.timestamp_created)
+            }
+            EVICTION_POLICY_ISSUER_DIVERSITY => {
+                let key_id = &record.record.metadata.key_id;
+                let candidate_issuer_count = self.get_non_host_records_count_by_issuer(
+                    key_id,
+                    &candidate.record.metadata.issued_by,
+                )?;
+                let record_issuer_count = self.get_non_host_records_count_by_issuer(
+                    key_id,
+                    &record.record.metadata.issued_by,
+                )?;
+                // evict if it makes issuers more even, fall back to weights otherwise
+                Ok(record_issuer_count + 1 < candidate_issuer_count || is_lighter_or_older)
+            }
+            other => Err(UnknownEvictionPolicy(other.to_string())),
         }
     }
 
@@ -138,9 +185,20 @@ impl Storage {
             record.record.metadata.peer_id.clone(),
             record.record.metadata.timestamp_issued,
         )?;
+        let is_stored = self.is_row_stored(
+            &record.record.metadata.key_id,
+            &record.record.metadata.issued_by,
+            &record.record.metadata.peer_id,
+            false,
+            &record.record.metadata.issuer_signature,
+            Value::Binary(record.record.signature.clone()),
+        )?;
+        let key_id = record.record.metadata.key_id.clone();
+        let issued_by = record.record.metadata.issued_by.clone();
+        let peer_id = record.record.metadata.peer_id.clone();
 
         let mut statement = self.connection.prepare(f!(
-            "INSERT OR REPLACE INTO {RECORDS_TABLE_NAME} VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);"
+            "INSERT OR REPLACE INTO {RECORDS_TABLE_NAME} VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);"
         ))?;
 
         let is_tombstoned = 0;
@@ -169,12 +227,82 @@ impl Storage {
         statement.bind(11, &Value::Integer(record.record.timestamp_created as i64))?;
         statement.bind(12, &Value::Binary(record.record.signature))?;
         statement.bind(13, &Value::Integer(record.weight as i64))?;
+        statement.bind(
+            14,
+            &record
+                .record
+                .metadata
+                .ttl
+                .first()
+                .map_or(Value::Null, |ttl| Value::Integer(*ttl as i64)),
+        )?;
 
         statement.next().map(drop)?;
 
+        if !is_stored {
+            self.log_change(&key_id, CHANGE_RECORD_UPSERTED, &issued_by, &peer_id)?;
+        }
+
         Ok(())
     }
 
+    /// Check if a record (not a tombstone) with `(key_id, issued_by, peer_id)` is stored
+    fn is_record_stored(
+        &self,
+        key_id: &str,
+        issued_by: &str,
+        peer_id: &str,
+    ) -> Result<bool, ServiceError> {
+        let mut statement =
+            self.connection
+                .prepare(f!(
+                    "SELECT EXISTS (SELECT 1 FROM {RECORDS_TABLE_NAME} \
+             WHERE key_id=? AND issued_by=? AND peer_id=? AND is_tombstoned=0 LIMIT 1)"
+                ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.bind(2, &Value::String(issued_by.to_string()))?;
+        statement.bind(3, &Value::String(peer_id.to_string()))?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<i64>(0)? == 1)
+        } else {
+            Err(InternalError(
+                "EXISTS should always return something".to_string(),
+            ))
+        }
+    }
+
+    /// Check if exactly the same record or tombstone is already stored,
+    /// `signature` is NULL for tombstones
+    pub fn is_row_stored(
+        &self,
+        key_id: &str,
+        issued_by: &str,
+        peer_id: &str,
+        is_tombstoned: bool,
+        issuer_signature: &[u8],
+        signature: Value,
+    ) -> Result<bool, ServiceError> {
+        let mut statement = self.connection.prepare(f!(
+            "SELECT EXISTS (SELECT 1 FROM {RECORDS_TABLE_NAME} WHERE key_id=? AND issued_by=? AND peer_id=? \
+                AND is_tombstoned=? AND issuer_signature=? AND signature IS ? LIMIT 1)"
+        ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.bind(2, &Value::String(issued_by.to_string()))?;
+        statement.bind(3, &Value::String(peer_id.to_string()))?;
+        statement.bind(4, &Value::Integer(is_tombstoned as i64))?;
+        statement.bind(5, &Value::Binary(issuer_signature.to_vec()))?;
+        statement.bind(6, &signature)?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<i64>(0)? == 1)
+        } else {
+            Err(InternalError(
+                "EXISTS should always return something".to_string(),
+            ))
+        }
+    }
+
     pub fn delete_record(
         &self,
         key_id: String,
@@ -193,16 +321,45 @@ impl Storage {
         Ok(self.connection.changes() == 1)
     }
 
-    fn get_min_weight_non_host_record_by_key(
+    /// Remove all records and tombstones of the key
+    pub fn delete_records_by_key(&self, key_id: &str) -> Result<u64, ServiceError> {
+        let mut statement = self
+            .connection
+            .prepare(f!("DELETE FROM {RECORDS_TABLE_NAME} WHERE key_id=?"))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.next().map(drop)?;
+
+        Ok(self.connection.changes() as u64)
+    }
+
+    /// The first non-host record to evict: the lightest and then the oldest one for weight policy,
+    /// the oldest for recency policy and the lightest record of the most frequent issuer for
+    /// issuer diversity policy
+    fn get_eviction_candidate(
         &self,
         key_id: &str,
+        policy: &str,
     ) -> Result<RecordInternal, ServiceError> {
         let host_id = marine_rs_sdk::get_call_parameters().host_id;
+        let (condition, order) = match policy {
+            EVICTION_POLICY_WEIGHT => ("".to_string(), "weight ASC, timestamp_created ASC"),
+            EVICTION_POLICY_RECENCY => ("".to_string(), "timestamp_created ASC, weight ASC"),
+            EVICTION_POLICY_ISSUER_DIVERSITY => (
+                f!(
+                    "AND issued_by = (SELECT issued_by FROM {RECORDS_TABLE_NAME} \
+                    WHERE key_id = ?1 AND peer_id != ?2 AND is_tombstoned = 0 \
+                    GROUP BY issued_by ORDER BY COUNT(*) DESC, issued_by LIMIT 1)"
+                ),
+                "weight ASC, timestamp_created ASC",
+            ),
+            other => return Err(UnknownEvictionPolicy(other.to_string())),
+        };
 
         // only only non-host values
         let mut statement = self.connection.prepare(
-            f!("SELECT key_id, value, peer_id, set_by, relay_id, service_id, timestamp_created, signature, weight FROM {RECORDS_TABLE_NAME} \
-                     WHERE key_id = ? AND peer_id != ? AND is_tombstoned = 0 ORDER BY weight ASC LIMIT 1"))?;
+            f!("SELECT key_id, issued_by, peer_id, timestamp_issued, solution, issuer_signature, \
+                    value, relay_id, service_id, timestamp_created, signature, weight, ttl FROM {RECORDS_TABLE_NAME} \
+                     WHERE key_id = ?1 AND peer_id != ?2 AND is_tombstoned = 0 {condition} ORDER BY {order} LIMIT 1"))?;
 
         statement.bind(1, &Value::String(key_id.to_string()))?;
         statement.bind(2, &Value::String(host_id))?;
@@ -216,6 +373,31 @@ impl Storage {
         }
     }
 
+    fn get_non_host_records_count_by_issuer(
+        &self,
+        key_id: &str,
+        issued_by: &str,
+    ) -> Result<usize, ServiceError> {
+        let host_id = marine_rs_sdk::get_call_parameters().host_id;
+        let mut statement =
+            self.connection
+                .prepare(f!(
+                    "SELECT COUNT(*) FROM {RECORDS_TABLE_NAME} \
+             WHERE key_id = ? AND issued_by = ? AND peer_id != ? AND is_tombstoned = 0"
+                ))?;
+        statement.bind(1, &Value::String(key_id.to_string()))?;
+        statement.bind(2, &Value::String(issued_by.to_string()))?;
+        statement.bind(3, &Value::String(host_id))?;
+
+        if let State::Row = statement.next()? {
+            Ok(statement.read::<i64>(0)? as usize)
+        } else {
+            Err(InternalError(
+                "COUNT should always return something".to_string(),
+            ))
+        }
+    }
+
     fn get_non_host_records_count_by_key(&self, key_id: &str) -> Result<usize, ServiceError> {
         println!("get_non_host_records_count_by_key");
 
@@ -265,20 +447,22 @@ impl Storage {
         records: Vec<RecordInternal>,
         current_timestamp_sec: u64,
     ) -> Result<u64, ServiceError> {
-        let records = merge_records(
-            self.get_records(key_id, current_timestamp_sec)?
-                .into_iter()
-                .chain(records.into_iter())
-                .collect(),
-        )?;
-
-        let mut updated = 0u64;
-        for record in records.into_iter() {
-            self.write_record(record)?;
-            updated += self.connection.changes() as u64;
-        }
+        self.transaction(|| {
+            let records = merge_records(
+                self.get_records(key_id, current_timestamp_sec)?
+                    .into_iter()
+                    .chain(records.into_iter())
+                    .collect(),
+            )?;
+
+            let mut updated = 0u64;
+            for record in records.into_iter() {
+                self.write_record(record)?;
+                updated += self.connection.changes() as u64;
+            }
 
-        Ok(updated)
+            Ok(updated)
+        })
     }
 
     pub fn get_records(
@@ -286,14 +470,14 @@ impl Storage {
         key_id: String,
         current_timestamp_sec: u64,
     ) -> Result<Vec<RecordInternal>, ServiceError> {
+        let expires_at = expires_at(&load_expiry_policy()?);
         let mut statement = self.connection.prepare(f!(
             "SELECT key_id, issued_by, peer_id, timestamp_issued, solution, issuer_signature,\
-                    value, relay_id, service_id, timestamp_created, signature \
-             FROM {RECORDS_TABLE_NAME} WHERE key_id = ? AND is_tombstoned = 0 AND timestamp_created > ? ORDER BY weight DESC"
+                    value, relay_id, service_id, timestamp_created, signature, weight, ttl \
+             FROM {RECORDS_TABLE_NAME} WHERE key_id = ? AND is_tombstoned = 0 AND {expires_at} > ? ORDER BY weight DESC"
         ))?;
-        let expired_timestamp = current_timestamp_sec - load_config().expired_timeout;
         statement.bind(1, &Value::String(key_id))?;
-        statement.bind(2, &Value::Integer(expired_timestamp as i64))?;
+        statement.bind(2, &Value::Integer(current_timestamp_sec as i64))?;
 
         let mut result: Vec<RecordInternal> = vec![];
 
@@ -304,15 +488,85 @@ impl Storage {
         Ok(result)
     }
 
+    pub fn add_records_ttl_column(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!(
+            "ALTER TABLE {RECORDS_TABLE_NAME} ADD COLUMN ttl INTEGER;"
+        ))?;
+        Ok(())
+    }
+
+    pub fn create_records_lookup_indexes(&self) -> Result<(), ServiceError> {
+        self.connection.execute(f!("
+            CREATE INDEX IF NOT EXISTS records_service_id_index ON {RECORDS_TABLE_NAME} (service_id);
+            CREATE INDEX IF NOT EXISTS records_peer_id_index ON {RECORDS_TABLE_NAME} (peer_id);
+            CREATE INDEX IF NOT EXISTS records_issued_by_index ON {RECORDS_TABLE_NAME} (issued_by);
+        "))?;
+        Ok(())
+    }
+
+    /// Non-expired records of all keys advertising the service
+    pub fn get_records_by_service_id(
+        &self,
+        service_id: String,
+        current_timestamp_sec: u64,
+    ) -> Result<Vec<RecordInternal>, ServiceError> {
+        self.get_records_by_column("service_id", service_id, current_timestamp_sec)
+    }
+
+    /// Non-expired records of all keys hosted by the peer
+    pub fn get_records_by_peer_id(
+        &self,
+        peer_id: String,
+        current_timestamp_sec: u64,
+    ) -> Result<Vec<RecordInternal>, ServiceError> {
+        self.get_records_by_column("peer_id", peer_id, current_timestamp_sec)
+    }
+
+    /// Non-expired records of all keys issued by the peer
+    pub fn get_records_by_issuer(
+        &self,
+        issued_by: String,
+        current_timestamp_sec: u64,
+    ) -> Result<Vec<RecordInternal>, ServiceError> {
+        self.get_records_by_column("issued_by", issued_by, current_timestamp_sec)
+    }
+
+    fn get_records_by_column(
+        &self,
+        column: &str,
+        value: String,
+        current_timestamp_sec: u64,
+    ) -> Result<Vec<RecordInternal>, ServiceError> {
+        let expires_at = expires_at(&load_expiry_policy()?);
+        let mut statement = self.connection.prepare(f!(
+            "SELECT key_id, issued_by, peer_id, timestamp_issued, solution, issuer_signature,\
+                    value, relay_id, service_id, timestamp_created, signature, weight, ttl \
+             FROM {RECORDS_TABLE_NAME} WHERE {column} = ? AND is_tombstoned = 0 AND {expires_at} > ? \
+             ORDER BY key_id, weight DESC"
+        ))?;
+        statement.bind(1, &Value::String(value))?;
+        statement.bind(2, &Value::Integer(current_timestamp_sec as i64))?;
+
+        let mut result: Vec<RecordInternal> = vec![];
+
+        while let State::Row = statement.next()? {
+            result.push(read_record(&statement)?)
+        }
+
+        Ok(result)
+    }
+
+    /// Host records which expire before `stale_timestamp_sec`
     pub fn get_local_stale_records(
         &self,
         stale_timestamp_sec: u64,
     ) -> Result<Vec<RecordInternal>, ServiceError> {
         let host_id = marine_rs_sdk::get_call_parameters().host_id;
+        let expires_at = expires_at(&load_expiry_policy()?);
         let mut statement = self.connection.prepare(f!(
             "SELECT key_id, issued_by, peer_id, timestamp_issued, solution, issuer_signature,\
-                    value, relay_id, service_id, timestamp_created, signature \
-             FROM {RECORDS_TABLE_NAME} WHERE peer_id = ? AND is_tombstoned = 0 AND timestamp_created < ?"
+                    value, relay_id, service_id, timestamp_created, signature, weight, ttl \
+             FROM {RECORDS_TABLE_NAME} WHERE peer_id = ? AND is_tombstoned = 0 AND {expires_at} < ?"
         ))?;
         statement.bind(1, &Value::String(host_id))?;
         statement.bind(2, &Value::Integer(stale_timestamp_sec as i64))?;
@@ -328,11 +582,15 @@ impl Storage {
 
     /// Remove expired records except host records (actually we should not have expired host records
     /// at this stage, all host records should be updated in time or removed via tombstones)
-    pub fn clear_expired_records(&self, expired_timestamp: u64) -> Result<u64, ServiceError> {
+    pub fn clear_expired_records(&self, current_timestamp_sec: u64) -> Result<u64, ServiceError> {
         let host_id = marine_rs_sdk::get_call_parameters().host_id;
-        self.connection.execute(f!(
-            "DELETE FROM {RECORDS_TABLE_NAME} WHERE timestamp_created <= {expired_timestamp} AND peer_id != '{host_id}' AND is_tombstoned = 0"
-        ))?;
+        let expires_at = expires_at(&load_expiry_policy()?);
+        let condition = f!(
+            "{expires_at} <= {current_timestamp_sec} AND peer_id != '{host_id}' AND is_tombstoned = 0"
+        );
+        self.log_records_changes(&condition, CHANGE_EXPIRED)?;
+        self.connection
+            .execute(f!("DELETE FROM {RECORDS_TABLE_NAME} WHERE {condition}"))?;
         Ok(self.connection.changes() as u64)
     }
 }
@@ -350,6 +608,11 @@ pub fn read_record(statement: &Statement) -> Result<RecordInternal, ServiceError
                 value: statement.read::<String>(6)?,
                 relay_id: get_custom_option(statement.read::<String>(7)?),
                 service_id: get_custom_option(statement.read::<String>(8)?),
+                ttl: statement
+                    .read::<Option<i64>>(12)?
+                    .map(|ttl| ttl as u64)
+                    .into_iter()
+                    .collect(),
             },
             timestamp_created: statement.read::<i64>(9)? as u64,
             signature: statement.read::<Vec<u8>>(10)?,
@@ -358,10 +621,18 @@ pub fn read_record(statement: &Statement) -> Result<RecordInternal, ServiceError
     })
 }
 
-/// Merge values with same peer_id by timestamp_created (last-write-wins)
+/// SQL expression for the timestamp when a record expires, see `ExpiryPolicy::record_ttl`
+fn expires_at(expiry_policy: &ExpiryPolicy) -> String {
+    let max_record_ttl = expiry_policy.max_record_ttl;
+    let expired_timeout = expiry_policy.expired_timeout;
+    f!("(timestamp_created + COALESCE(MIN(ttl, {max_record_ttl}), {expired_timeout}))")
+}
+
+/// Merge values with same peer_id by timestamp_created (last-write-wins),
+/// result is ordered by (peer_id, issued_by)
 pub fn merge_records(records: Vec<RecordInternal>) -> Result<Vec<RecordInternal>, ServiceError> {
-    // key is (peer_id, set_by)
-    let mut result: HashMap<(String, String), RecordInternal> = HashMap::new();
+    // key is (peer_id, issued_by)
+    let mut result: BTreeMap<(String, String), RecordInternal> = BTreeMap::new();
 
     for rec in records.into_iter() {
         let key = (
@@ -14,8 +14,13 @@
  * limitations under the License.
  */
 
+use crate::change::Change;
+use crate::config::Config;
+use crate::digest::{KeyDigest, MerkleNode};
 use crate::error::ServiceError;
-use crate::key::Key;
+use crate::key::{Key, KeyInternal};
+use crate::key_tombstone::KeyTombstone;
+use crate::key_transfer::KeyTransfer;
 use crate::record::Record;
 use crate::tombstone::Tombstone;
 use marine_rs_sdk::marine;
@@ -125,17 +130,19 @@ pub struct ClearExpiredResult {
     pub count_keys: u64,
     pub count_records: u64,
     pub count_tombstones: u64,
+    pub count_key_tombstones: u64,
 }
 
-impl From<Result<(u64, u64, u64), ServiceError>> for ClearExpiredResult {
-    fn from(result: Result<(u64, u64, u64), ServiceError>) -> Self {
+impl From<Result<(u64, u64, u64, u64), ServiceError>> for ClearExpiredResult {
+    fn from(result: Result<(u64, u64, u64, u64), ServiceError>) -> Self {
         match result {
-            Ok((count_keys, count_records, count_tombstones)) => Self {
+            Ok((count_keys, count_records, count_tombstones, count_key_tombstones)) => Self {
                 success: true,
                 error: "".to_string(),
                 count_keys,
                 count_records,
                 count_tombstones,
+                count_key_tombstones,
             },
             Err(err) => Self {
                 success: false,
@@ -143,6 +150,7 @@ impl From<Result<(u64, u64, u64), ServiceError>> for ClearExpiredResult {
                 count_keys: 0,
                 count_records: 0,
                 count_tombstones: 0,
+                count_key_tombstones: 0,
             },
         }
     }
@@ -224,29 +232,45 @@ impl From<Result<u64, ServiceError>> for RepublishRecordsResult {
 #[marine]
 pub struct EvictStaleItem {
     pub key: Key,
+    /// the key transfer chain ordered by `timestamp_issued`, required to republish keys signed by later owners
+    pub transfers: Vec<KeyTransfer>,
     pub records: Vec<Record>,
     pub tombstones: Vec<Tombstone>,
 }
 
+#[marine]
+pub struct EvictStaleKeyTombstone {
+    pub tombstone: KeyTombstone,
+    /// the key transfer chain the tombstone was verified with, required to republish it to peers without the key
+    pub transfers: Vec<KeyTransfer>,
+}
+
 #[marine]
 pub struct EvictStaleResult {
     pub success: bool,
     pub error: String,
     pub results: Vec<EvictStaleItem>,
+    pub key_tombstones: Vec<EvictStaleKeyTombstone>,
 }
 
-impl From<Result<Vec<EvictStaleItem>, ServiceError>> for EvictStaleResult {
-    fn from(result: Result<Vec<EvictStaleItem>, ServiceError>) -> Self {
+impl From<Result<(Vec<EvictStaleItem>, Vec<EvictStaleKeyTombstone>), ServiceError>>
+    for EvictStaleResult
+{
+    fn from(
+        result: Result<(Vec<EvictStaleItem>, Vec<EvictStaleKeyTombstone>), ServiceError>,
+    ) -> Self {
         match result {
-            Ok(results) => Self {
+            Ok((results, key_tombstones)) => Self {
                 success: true,
                 error: "".to_string(),
                 results,
+                key_tombstones,
             },
             Err(err) => Self {
                 success: false,
                 error: err.to_string(),
                 results: vec![],
+                key_tombstones: vec![],
             },
         }
     }
@@ -300,3 +324,361 @@ impl From<Result<Key, ServiceError>> for MergeKeysResult {
         }
     }
 }
+
+#[marine]
+pub struct GetKeyTombstoneResult {
+    pub success: bool,
+    pub error: String,
+    pub tombstone: KeyTombstone,
+}
+
+impl From<Result<KeyTombstone, ServiceError>> for GetKeyTombstoneResult {
+    fn from(result: Result<KeyTombstone, ServiceError>) -> Self {
+        match result {
+            Ok(tombstone) => Self {
+                success: true,
+                error: "".to_string(),
+                tombstone,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                tombstone: KeyTombstone::default(),
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct GetKeyTransferResult {
+    pub success: bool,
+    pub error: String,
+    /// empty if the key wasn't transferred
+    pub transfer: Vec<KeyTransfer>,
+}
+
+impl From<Result<Option<KeyTransfer>, ServiceError>> for GetKeyTransferResult {
+    fn from(result: Result<Option<KeyTransfer>, ServiceError>) -> Self {
+        match result {
+            Ok(transfer) => Self {
+                success: true,
+                error: "".to_string(),
+                transfer: transfer.into_iter().collect(),
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                transfer: vec![],
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct GetKeysResult {
+    pub success: bool,
+    pub error: String,
+    pub keys: Vec<Key>,
+    /// cursor for the next page, empty if there are no more keys
+    pub next_cursor: String,
+}
+
+impl From<Result<(Vec<Key>, String), ServiceError>> for GetKeysResult {
+    fn from(result: Result<(Vec<Key>, String), ServiceError>) -> Self {
+        match result {
+            Ok((keys, next_cursor)) => Self {
+                success: true,
+                error: "".to_string(),
+                keys,
+                next_cursor,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                keys: vec![],
+                next_cursor: "".to_string(),
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct WeightedKey {
+    pub key: Key,
+    /// weight of key.owner_peer_id in local TrustGraph
+    pub weight: u32,
+}
+
+#[marine]
+pub struct SearchKeysResult {
+    pub success: bool,
+    pub error: String,
+    pub keys: Vec<WeightedKey>,
+    /// cursor for the next page, empty if there are no more keys
+    pub next_cursor: String,
+}
+
+impl From<Result<(Vec<KeyInternal>, String), ServiceError>> for SearchKeysResult {
+    fn from(result: Result<(Vec<KeyInternal>, String), ServiceError>) -> Self {
+        match result {
+            Ok((keys, next_cursor)) => Self {
+                success: true,
+                error: "".to_string(),
+                keys: keys
+                    .into_iter()
+                    .map(|k| WeightedKey {
+                        key: k.key,
+                        weight: k.weight,
+                    })
+                    .collect(),
+                next_cursor,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                keys: vec![],
+                next_cursor: "".to_string(),
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct PutRecordsResult {
+    pub success: bool,
+    pub error: String,
+    /// result for each record in the same order
+    pub results: Vec<RegistryResult>,
+}
+
+impl From<Result<Vec<RegistryResult>, ServiceError>> for PutRecordsResult {
+    fn from(result: Result<Vec<RegistryResult>, ServiceError>) -> Self {
+        match result {
+            Ok(results) => Self {
+                success: true,
+                error: "".to_string(),
+                results,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                results: vec![],
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct ResolvedKey {
+    pub key_id: String,
+    pub success: bool,
+    pub error: String,
+    pub key: Key,
+    pub records: Vec<Record>,
+    pub tombstones: Vec<Tombstone>,
+}
+
+impl ResolvedKey {
+    pub fn new(
+        key_id: String,
+        result: Result<(Key, Vec<Record>, Vec<Tombstone>), ServiceError>,
+    ) -> Self {
+        match result {
+            Ok((key, records, tombstones)) => Self {
+                key_id,
+                success: true,
+                error: "".to_string(),
+                key,
+                records,
+                tombstones,
+            },
+            Err(err) => Self {
+                key_id,
+                success: false,
+                error: err.to_string(),
+                key: Key::default(),
+                records: vec![],
+                tombstones: vec![],
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct ResolveKeysResult {
+    pub success: bool,
+    pub error: String,
+    /// result for each key in the same order
+    pub results: Vec<ResolvedKey>,
+}
+
+impl From<Result<Vec<ResolvedKey>, ServiceError>> for ResolveKeysResult {
+    fn from(result: Result<Vec<ResolvedKey>, ServiceError>) -> Self {
+        match result {
+            Ok(results) => Self {
+                success: true,
+                error: "".to_string(),
+                results,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                results: vec![],
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct KeyDigestResult {
+    pub success: bool,
+    pub error: String,
+    pub digest: Vec<u8>,
+}
+
+impl From<Result<Vec<u8>, ServiceError>> for KeyDigestResult {
+    fn from(result: Result<Vec<u8>, ServiceError>) -> Self {
+        match result {
+            Ok(digest) => Self {
+                success: true,
+                error: "".to_string(),
+                digest,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                digest: vec![],
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct KeyDigestsResult {
+    pub success: bool,
+    pub error: String,
+    pub digests: Vec<KeyDigest>,
+}
+
+impl From<Result<Vec<KeyDigest>, ServiceError>> for KeyDigestsResult {
+    fn from(result: Result<Vec<KeyDigest>, ServiceError>) -> Self {
+        match result {
+            Ok(digests) => Self {
+                success: true,
+                error: "".to_string(),
+                digests,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                digests: vec![],
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct MerkleNodeResult {
+    pub success: bool,
+    pub error: String,
+    pub node: MerkleNode,
+    pub children: Vec<MerkleNode>,
+}
+
+impl From<Result<(MerkleNode, Vec<MerkleNode>), ServiceError>> for MerkleNodeResult {
+    fn from(result: Result<(MerkleNode, Vec<MerkleNode>), ServiceError>) -> Self {
+        match result {
+            Ok((node, children)) => Self {
+                success: true,
+                error: "".to_string(),
+                node,
+                children,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                node: MerkleNode::default(),
+                children: vec![],
+            },
+        }
+    }
+}
+
+#[marine]
+pub struct GetChangesResult {
+    pub success: bool,
+    pub error: String,
+    pub changes: Vec<Change>,
+    /// sequence number of the latest change
+    pub last_seq: u64,
+}
+
+impl From<Result<(Vec<Change>, u64), ServiceError>> for GetChangesResult {
+    fn from(result: Result<(Vec<Change>, u64), ServiceError>) -> Self {
+        match result {
+            Ok((changes, last_seq)) => Self {
+                success: true,
+                error: "".to_string(),
+                changes,
+                last_seq,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                changes: vec![],
+                last_seq: 0,
+            },
+        }
+    }
+}
+
+#[marine]
+#[derive(Debug, Default)]
+pub struct GetConfigResult {
+    pub success: bool,
+    pub error: String,
+    pub expired_timeout: u64,
+    pub stale_timeout: u64,
+    pub max_record_ttl: u64,
+    pub tombstone_retention: u64,
+    pub records_limit: u64,
+    pub max_key_records_limit: u64,
+    pub eviction_policy: String,
+    pub quota_window: u64,
+    pub key_writes_quota: u64,
+    pub record_writes_quota: u64,
+    pub trusted_weight: u32,
+    pub trusted_quota_multiplier: u64,
+    pub accept_v1_signatures: bool,
+    pub admins: Vec<String>,
+}
+
+impl From<Result<Config, ServiceError>> for GetConfigResult {
+    fn from(result: Result<Config, ServiceError>) -> Self {
+        match result {
+            Ok(config) => Self {
+                success: true,
+                error: "".to_string(),
+                expired_timeout: config.expired_timeout,
+                stale_timeout: config.stale_timeout,
+                max_record_ttl: config.max_record_ttl,
+                tombstone_retention: config.tombstone_retention,
+                records_limit: config.records_limit,
+                max_key_records_limit: config.max_key_records_limit,
+                eviction_policy: config.eviction_policy,
+                quota_window: config.quota_window,
+                key_writes_quota: config.key_writes_quota,
+                record_writes_quota: config.record_writes_quota,
+                trusted_weight: config.trusted_weight,
+                trusted_quota_multiplier: config.trusted_quota_multiplier,
+                accept_v1_signatures: config.accept_v1_signatures,
+                admins: config.admins,
+            },
+            Err(err) => Self {
+                success: false,
+                error: err.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+}
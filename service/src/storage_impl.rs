@@ -14,12 +14,14 @@
  * limitations under the License.
  */
 
-use crate::config::load_config;
-use crate::defaults::DB_PATH;
 use crate::error::ServiceError;
+use crate::expiry_policy::load_expiry_policy;
+use crate::key::Key;
 use crate::record::Record;
-use crate::results::EvictStaleItem;
+use crate::results::{EvictStaleItem, EvictStaleKeyTombstone};
+use crate::tombstone::Tombstone;
 use marine_sqlite_connector::{Connection, Result as SqliteResult, State, Value};
+use registry_client::misc::get_db_path;
 
 pub struct Storage {
     pub(crate) connection: Connection,
@@ -27,23 +29,19 @@ pub struct Storage {
 
 #[inline]
 pub(crate) fn get_storage() -> SqliteResult<Storage> {
-    marine_sqlite_connector::open(DB_PATH).map(|c| Storage { connection: c })
+    marine_sqlite_connector::open(get_db_path()).map(|c| Storage { connection: c })
 }
 
-pub fn get_custom_option(value: String) -> Vec<String> {
-    if value.is_empty() {
-        vec![]
-    } else {
-        vec![value]
-    }
-}
-
-pub fn from_custom_option(value: Vec<String>) -> String {
-    if value.is_empty() {
-        "".to_string()
-    } else {
-        value[0].clone()
-    }
+/// SQLite stores `CREATE TABLE` statements in `sqlite_master` with collapsed whitespaces
+/// and without `IF NOT EXISTS` clause, so schemas should be compared in this form
+fn normalize_schema(schema: &str) -> String {
+    schema
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("CREATE TABLE IF NOT EXISTS", "CREATE TABLE")
+        .trim_end_matches(';')
+        .to_string()
 }
 
 impl Storage {
@@ -62,33 +60,100 @@ impl Storage {
         }
     }
 
-    pub fn delete_table(&self, table_name: String) -> Result<(), ServiceError> {
-        self.connection
-            .execute(f!("DROP TABLE IF EXISTS {table_name};"))?;
-        Ok(())
+    /// Check that the table either doesn't exist or was created with `table_schema`
+    pub fn check_table_schema(
+        &self,
+        table_name: &str,
+        table_schema: &str,
+    ) -> Result<(), ServiceError> {
+        let current_table_schema = self.get_table_schema(table_name.to_string())?;
+        if current_table_schema.is_empty()
+            || normalize_schema(&current_table_schema) == normalize_schema(table_schema)
+        {
+            Ok(())
+        } else {
+            Err(ServiceError::UnexpectedTableSchema(
+                table_name.to_string(),
+                current_table_schema,
+            ))
+        }
     }
 
-    /// Remove expired records (based on `timestamp_created`), expired tombstones (based on `timestamp_issued`)
-    /// and then expired keys without actual records
-    pub fn clear_expired(
+    /// Run `f` inside a savepoint: all changes are released on success
+    /// and rolled back if `f` returns an error. Savepoints can be nested.
+    pub fn transaction<T, F>(&self, f: F) -> Result<T, ServiceError>
+    where
+        F: FnOnce() -> Result<T, ServiceError>,
+    {
+        self.connection.execute("SAVEPOINT registry_transaction;")?;
+        match f() {
+            Ok(result) => {
+                self.connection
+                    .execute("RELEASE SAVEPOINT registry_transaction;")?;
+                Ok(result)
+            }
+            Err(error) => {
+                self.connection.execute(
+                    "ROLLBACK TO SAVEPOINT registry_transaction; RELEASE SAVEPOINT registry_transaction;",
+                )?;
+                Err(error)
+            }
+        }
+    }
+
+    /// Key metadata with its live records and tombstones
+    pub fn resolve_key(
         &self,
+        key_id: String,
         current_timestamp_sec: u64,
-    ) -> Result<(u64, u64, u64), ServiceError> {
-        let config = load_config();
-
-        let expired_timestamp = current_timestamp_sec - config.expired_timeout;
-        let deleted_tombstones = self.clear_expired_tombstones(expired_timestamp)?;
-        let deleted_records = self.clear_expired_records(expired_timestamp)?;
-        let deleted_keys = self.clear_expired_keys(expired_timestamp)?;
+    ) -> Result<(Key, Vec<Record>, Vec<Tombstone>), ServiceError> {
+        let key = self.get_key(key_id.clone())?;
+        let records = self
+            .get_records(key_id.clone(), current_timestamp_sec)?
+            .into_iter()
+            .map(|r| r.record)
+            .collect();
+        let tombstones = self.get_tombstones(key_id, current_timestamp_sec)?;
+
+        Ok((key, records, tombstones))
+    }
 
-        Ok((deleted_keys, deleted_records, deleted_tombstones))
+    /// Remove expired records (based on `timestamp_created` and ttl), tombstones older than the tombstone
+    /// retention (based on `timestamp_issued`), expired key tombstones (based on `timestamp_issued`)
+    /// and then expired keys without actual records. Transfer chains of removed keys
+    /// and quota counters of finished windows are removed too
+    pub fn clear_expired(
+        &self,
+        current_timestamp_sec: u64,
+    ) -> Result<(u64, u64, u64, u64), ServiceError> {
+        let expiry_policy = load_expiry_policy()?;
+        let expired_timestamp = expiry_policy.expired_timestamp(current_timestamp_sec);
+        let tombstone_expired_timestamp =
+            expiry_policy.tombstone_expired_timestamp(current_timestamp_sec);
+        self.transaction(|| {
+            let deleted_tombstones = self.clear_expired_tombstones(tombstone_expired_timestamp)?;
+            let deleted_records = self.clear_expired_records(current_timestamp_sec)?;
+            let deleted_keys = self.clear_expired_keys(expired_timestamp)?;
+            let deleted_key_tombstones =
+                self.clear_expired_key_tombstones(tombstone_expired_timestamp)?;
+            self.clear_orphan_key_transfers()?;
+            self.trim_changes()?;
+            self.clear_expired_quotas(current_timestamp_sec)?;
+
+            Ok((
+                deleted_keys,
+                deleted_records,
+                deleted_tombstones,
+                deleted_key_tombstones,
+            ))
+        })
     }
 
     pub fn evict_stale(
         &self,
         current_timestamp_sec: u64,
-    ) -> Result<Vec<EvictStaleItem>, ServiceError> {
-        let stale_timestamp = current_timestamp_sec - load_config().stale_timeout;
+    ) -> Result<(Vec<EvictStaleItem>, Vec<EvictStaleKeyTombstone>), ServiceError> {
+        let stale_timestamp = load_expiry_policy()?.stale_timestamp(current_timestamp_sec);
 
         let stale_keys = self.get_stale_keys(stale_timestamp)?;
         let mut results: Vec<EvictStaleItem> = vec![];
@@ -100,13 +165,24 @@ impl Storage {
                 .collect();
 
             let tombstones = self.get_tombstones(key.key.id.clone(), current_timestamp_sec)?;
+            let transfers = self.get_key_transfers(&key.key.id)?;
             results.push(EvictStaleItem {
                 key: key.key,
+                transfers,
                 records,
                 tombstones,
             });
         }
 
-        Ok(results)
+        let key_tombstones = self
+            .get_stale_key_tombstones(stale_timestamp)?
+            .into_iter()
+            .map(|(tombstone, transfers)| EvictStaleKeyTombstone {
+                tombstone,
+                transfers,
+            })
+            .collect();
+
+        Ok((results, key_tombstones))
     }
 }
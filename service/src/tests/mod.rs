@@ -19,22 +19,30 @@ mod tests {
     use std::fs;
 
     use rusqlite::Connection;
+    use sha2::{Digest, Sha256};
     marine_rs_sdk_test::include_test_env!("/marine_test_env.rs");
     use marine_rs_sdk_test::{CallParameters, SecurityTetraplet};
     use marine_test_env::registry::{EvictStaleResult, Record, RegistryResult, ServiceInterface};
 
     use crate::defaults::{
-        CONFIG_FILE, DB_PATH, DEFAULT_EXPIRED_AGE, DEFAULT_STALE_AGE, KEYS_TABLE_NAME,
-        RECORDS_TABLE_NAME, TRUSTED_TIMESTAMP_FUNCTION_NAME, TRUSTED_TIMESTAMP_SERVICE_ID,
+        CHANGES_TABLE_NAME, CHANGE_EXPIRED, CHANGE_KEY_REGISTERED, CHANGE_RECORD_UPSERTED,
+        CHANGE_TOMBSTONE_ADDED, CONFIG_FILE, DB_PATH, DEFAULT_EXPIRED_AGE, DEFAULT_STALE_AGE,
+        DEFAULT_TOMBSTONE_RETENTION, KEYS_TABLE_NAME, KEY_TOMBSTONES_TABLE_NAME, QUOTAS_TABLE_NAME,
+        QUOTA_KIND_KEY_WRITES, QUOTA_KIND_RECORD_WRITES, RECORDS_LIMIT, RECORDS_TABLE_NAME,
+        SCHEMA_VERSION_TABLE_NAME, TRUSTED_TIMESTAMP_FUNCTION_NAME, TRUSTED_TIMESTAMP_SERVICE_ID,
         TRUSTED_WEIGHT_FUNCTION_NAME, TRUSTED_WEIGHT_SERVICE_ID,
     };
     use crate::error::ServiceError::{
-        InvalidKeyTimestamp, InvalidTimestampTetraplet, InvalidWeightPeerId,
-        KeyAlreadyExistsNewerTimestamp,
+        InvalidConfig, InvalidRecordTtl, InvalidTimestampTetraplet, InvalidWeightPeerId,
+        KeyAlreadyExistsNewerTimestamp, KeyNotExists, KeyTombstoneSignerUnknown, KeyTombstoned,
+        NotAdmin, NotHost, NotKeyOwner, QuotaExceeded, RecordAlreadyExpired, RecordsLimitTooLarge,
+        UnknownChallengeType, UnknownEvictionPolicy, UnknownLabelMatchType, ValuesLimitExceeded,
     };
+    use crate::migrations::MIGRATIONS;
     use crate::tests::tests::marine_test_env::registry::{
-        Key, RecordMetadata, RegisterKeyResult, Tombstone, WeightResult,
+        Key, MerkleNode, RecordMetadata, RegisterKeyResult, Tombstone, WeightResult,
     };
+    use registry_client::ClientError::{InvalidKeyTimestamp, KeyTransferNotByOwner};
 
     impl PartialEq for Key {
         fn eq(&self, other: &Self) -> bool {
@@ -57,12 +65,41 @@ mod tests {
         connection
             .execute(f!("DROP TABLE IF EXISTS {RECORDS_TABLE_NAME}").as_str(), [])
             .unwrap();
+        connection
+            .execute(
+                f!("DROP TABLE IF EXISTS {KEY_TOMBSTONES_TABLE_NAME}").as_str(),
+                [],
+            )
+            .unwrap();
+        connection
+            .execute(f!("DROP TABLE IF EXISTS {CHANGES_TABLE_NAME}").as_str(), [])
+            .unwrap();
+        connection
+            .execute(f!("DROP TABLE IF EXISTS {QUOTAS_TABLE_NAME}").as_str(), [])
+            .unwrap();
+        connection
+            .execute(
+                f!("DROP TABLE IF EXISTS {SCHEMA_VERSION_TABLE_NAME}").as_str(),
+                [],
+            )
+            .unwrap();
 
         if fs::metadata(CONFIG_FILE).is_ok() {
             fs::remove_file(CONFIG_FILE).unwrap();
         }
     }
 
+    fn get_schema_version() -> u64 {
+        let connection = Connection::open(DB_PATH).unwrap();
+        connection
+            .query_row(
+                f!("SELECT MAX(version) FROM {SCHEMA_VERSION_TABLE_NAME}").as_str(),
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+            .unwrap() as u64
+    }
+
     struct CPWrapper {
         pub cp: CallParameters,
     }
@@ -111,6 +148,15 @@ mod tests {
             self
         }
 
+        /// weight tetraplets for `count` elements of the array argument
+        fn add_weights_tetraplets(mut self, arg_number: usize, count: usize) -> Self {
+            self = self.add_weight_tetraplets(arg_number);
+            let tetraplet = self.cp.tetraplets[arg_number][0].clone();
+            self.cp.tetraplets[arg_number] = vec![tetraplet; count];
+
+            self
+        }
+
         pub fn get(&self) -> CallParameters {
             self.cp.clone()
         }
@@ -164,10 +210,30 @@ mod tests {
         timestamp_created: u64,
         current_timestamp: u64,
         weight: u32,
+    ) -> RegisterKeyResult {
+        register_key_with_challenge(
+            registry,
+            kp,
+            label,
+            timestamp_created,
+            current_timestamp,
+            weight,
+            vec![],
+            "".to_string(),
+        )
+    }
+
+    fn register_key_with_challenge(
+        registry: &mut ServiceInterface,
+        kp: &KeyPair,
+        label: String,
+        timestamp_created: u64,
+        current_timestamp: u64,
+        weight: u32,
+        challenge: Vec<u8>,
+        challenge_type: String,
     ) -> RegisterKeyResult {
         let issuer_peer_id = kp.get_peer_id().to_base58();
-        let challenge = vec![];
-        let challenge_type = "".to_string();
         let signature = get_signed_key_bytes(
             registry,
             kp,
@@ -249,6 +315,7 @@ mod tests {
         relay_id: Vec<String>,
         service_id: Vec<String>,
         solution: Vec<u8>,
+        ttl: Vec<u64>,
     ) -> Vec<u8> {
         let issued_by = kp.get_peer_id().to_base58();
         let key_bytes = registry.get_record_metadata_bytes(
@@ -260,6 +327,7 @@ mod tests {
             relay_id,
             service_id,
             solution,
+            ttl,
         );
         kp.sign(&key_bytes).unwrap().to_vec().to_vec()
     }
@@ -275,6 +343,7 @@ mod tests {
         relay_id: Vec<String>,
         service_id: Vec<String>,
         solution: Vec<u8>,
+        ttl: Vec<u64>,
     ) -> RecordMetadata {
         let signature = get_signed_record_metadata_bytes(
             registry,
@@ -286,6 +355,7 @@ mod tests {
             relay_id.clone(),
             service_id.clone(),
             solution.clone(),
+            ttl.clone(),
         );
         let issued_by = kp.get_peer_id().to_base58();
 
@@ -298,6 +368,7 @@ mod tests {
             relay_id,
             service_id,
             solution,
+            ttl,
             signature,
         )
     }
@@ -338,6 +409,7 @@ mod tests {
             relay_id,
             service_id,
             solution,
+            vec![],
         );
 
         let signature = get_signed_record_bytes(
@@ -477,6 +549,54 @@ mod tests {
         assert!(result.success, "{}", result.error);
     }
 
+    fn get_signed_key_tombstone_bytes(
+        registry: &mut ServiceInterface,
+        kp: &KeyPair,
+        label: String,
+        owner_peer_id: String,
+        timestamp_issued: u64,
+    ) -> Vec<u8> {
+        let bytes = registry.get_key_tombstone_bytes(label, vec![owner_peer_id], timestamp_issued);
+        kp.sign(&bytes).unwrap().to_vec().to_vec()
+    }
+
+    fn add_key_tombstone(
+        registry: &mut ServiceInterface,
+        kp: &KeyPair,
+        label: String,
+        timestamp_issued: u64,
+        current_timestamp: u64,
+    ) -> RegistryResult {
+        let owner_peer_id = kp.get_peer_id().to_base58();
+        let signature = get_signed_key_tombstone_bytes(
+            registry,
+            kp,
+            label.clone(),
+            owner_peer_id.clone(),
+            timestamp_issued,
+        );
+        let cp = CPWrapper::new(&owner_peer_id, "host_id").add_timestamp_tetraplets(4);
+        registry.add_key_tombstone_cp(
+            label,
+            vec![owner_peer_id],
+            timestamp_issued,
+            signature,
+            current_timestamp,
+            cp.get(),
+        )
+    }
+
+    fn add_key_tombstone_checked(
+        registry: &mut ServiceInterface,
+        kp: &KeyPair,
+        label: String,
+        timestamp_issued: u64,
+        current_timestamp: u64,
+    ) {
+        let result = add_key_tombstone(registry, kp, label, timestamp_issued, current_timestamp);
+        assert!(result.success, "{}", result.error);
+    }
+
     #[test]
     fn register_key_invalid_signature() {
         clear_env();
@@ -1029,4 +1149,2405 @@ mod tests {
         let tombstones = get_tombstones(&mut registry, key_id.clone(), current_timestamp);
         assert_eq!(tombstones.len(), 0);
     }
+
+    #[test]
+    fn migrations_are_ordered() {
+        let versions: Vec<u64> = MIGRATIONS.iter().map(|m| m.version).collect();
+        assert_eq!(versions[0], 1);
+        assert!(versions.windows(2).all(|w| w[0] < w[1]), "{:?}", versions);
+    }
+
+    #[test]
+    fn migrate_fresh_database() {
+        clear_env();
+        let _registry = ServiceInterface::new();
+
+        let latest_version = MIGRATIONS.last().unwrap().version;
+        assert_eq!(get_schema_version(), latest_version);
+    }
+
+    #[test]
+    fn migrate_preserves_data() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let label = "some_key".to_string();
+        let timestamp_created = 0u64;
+        let current_timestamp = 100u64;
+        let weight = 0;
+
+        let key_id = register_key_checked(
+            &mut registry,
+            &kp,
+            label,
+            timestamp_created,
+            current_timestamp,
+            weight,
+        );
+        let key = get_key_metadata(&mut registry, key_id.clone());
+
+        // restart on an up-to-date database
+        let mut registry = ServiceInterface::new();
+        assert_eq!(get_key_metadata(&mut registry, key_id), key);
+
+        let latest_version = MIGRATIONS.last().unwrap().version;
+        assert_eq!(get_schema_version(), latest_version);
+    }
+
+    #[test]
+    fn migrate_legacy_database() {
+        clear_env();
+        let connection = Connection::open(DB_PATH).unwrap();
+        connection
+            .execute(
+                f!("CREATE TABLE {KEYS_TABLE_NAME} (
+                    key_id TEXT PRIMARY KEY,
+                    label TEXT,
+                    owner_peer_id TEXT,
+                    timestamp_created INTEGER,
+                    challenge BLOB,
+                    challenge_type TEXT,
+                    signature BLOB NOT NULL,
+                    timestamp_published INTEGER,
+                    weight INTEGER
+                )")
+                .as_str(),
+                [],
+            )
+            .unwrap();
+        connection
+            .execute(
+                f!("CREATE TABLE {RECORDS_TABLE_NAME} (
+                    key_id TEXT,
+                    issued_by TEXT,
+                    peer_id TEXT,
+                    timestamp_issued INTEGER NOT NULL,
+                    solution BLOB,
+                    issuer_signature BLOB NOT NULL,
+                    is_tombstoned INTEGER NOT NULL,
+                    value TEXT,
+                    relay_id TEXT,
+                    service_id TEXT,
+                    timestamp_created INTEGER,
+                    signature BLOB,
+                    weight INTEGER,
+                    PRIMARY KEY (key_id, issued_by, peer_id)
+                )")
+                .as_str(),
+                [],
+            )
+            .unwrap();
+        connection
+            .execute(
+                f!("INSERT INTO {KEYS_TABLE_NAME} VALUES ('key_id', 'label', 'owner', 10, x'', '', x'01', 0, 0)")
+                    .as_str(),
+                [],
+            )
+            .unwrap();
+
+        let mut registry = ServiceInterface::new();
+        let key = get_key_metadata(&mut registry, "key_id".to_string());
+        assert_eq!(key.label, "label");
+        assert_eq!(key.owner_peer_id, "owner");
+        assert_eq!(key.timestamp_created, 10);
+
+        let latest_version = MIGRATIONS.last().unwrap().version;
+        assert_eq!(get_schema_version(), latest_version);
+    }
+
+    #[test]
+    fn add_key_tombstone_removes_key() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let label = "some_key".to_string();
+        let timestamp_created = 100u64;
+        let current_timestamp = 150u64;
+        let weight = 0;
+
+        let key_id = register_key_checked(
+            &mut registry,
+            &kp,
+            label.clone(),
+            timestamp_created,
+            current_timestamp,
+            weight,
+        );
+        let key = get_key_metadata(&mut registry, key_id.clone());
+        put_record_checked(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &host_kp,
+            timestamp_created,
+            current_timestamp,
+            "some_record_value".to_string(),
+            vec![],
+            vec![],
+            vec![],
+            weight,
+        );
+
+        add_key_tombstone_checked(
+            &mut registry,
+            &kp,
+            label.clone(),
+            current_timestamp,
+            current_timestamp,
+        );
+
+        let result = registry.get_key_metadata(key_id.clone());
+        assert!(!result.success);
+        assert_eq!(result.error, KeyNotExists(key_id.clone()).to_string());
+
+        let cp = CPWrapper::new("some_peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.get_records_cp(key_id.clone(), current_timestamp, cp.get());
+        assert!(!result.success);
+        assert_eq!(result.error, KeyNotExists(key_id.clone()).to_string());
+
+        // replication of the removed key is ignored
+        republish_key_checked(&mut registry, key, current_timestamp);
+        let result = registry.get_key_metadata(key_id.clone());
+        assert!(!result.success);
+
+        let result = register_key(
+            &mut registry,
+            &kp,
+            label.clone(),
+            timestamp_created + 10,
+            current_timestamp,
+            weight,
+        );
+        assert_eq!(result.error, KeyTombstoned(key_id.clone()).to_string());
+
+        // the owner can create the key again
+        register_key_checked(
+            &mut registry,
+            &kp,
+            label,
+            current_timestamp + 1,
+            current_timestamp + 1,
+            weight,
+        );
+        get_key_metadata(&mut registry, key_id);
+    }
+
+    #[test]
+    fn add_key_tombstone_invalid_signature() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+        let owner_peer_id = kp.get_peer_id().to_base58();
+        let label = "some_key".to_string();
+        let timestamp_created = 100u64;
+        let current_timestamp = 150u64;
+
+        let key_id = register_key_checked(
+            &mut registry,
+            &kp,
+            label.clone(),
+            timestamp_created,
+            current_timestamp,
+            0,
+        );
+
+        let signature = get_signed_key_tombstone_bytes(
+            &mut registry,
+            &other_kp,
+            label.clone(),
+            owner_peer_id.clone(),
+            current_timestamp,
+        );
+        let cp = CPWrapper::new(&owner_peer_id, "host_id").add_timestamp_tetraplets(4);
+        let result = registry.add_key_tombstone_cp(
+            label,
+            vec![owner_peer_id],
+            current_timestamp,
+            signature,
+            current_timestamp,
+            cp.get(),
+        );
+        assert!(!result.success);
+
+        get_key_metadata(&mut registry, key_id);
+    }
+
+    #[test]
+    fn evict_stale_and_clear_expired_key_tombstones() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let label = "some_key".to_string();
+        let timestamp_created = 100u64;
+        let mut current_timestamp = 100u64;
+
+        let key_id = register_key_checked(
+            &mut registry,
+            &kp,
+            label.clone(),
+            timestamp_created,
+            current_timestamp,
+            0,
+        );
+        add_key_tombstone_checked(
+            &mut registry,
+            &kp,
+            label,
+            current_timestamp,
+            current_timestamp,
+        );
+
+        current_timestamp += DEFAULT_STALE_AGE;
+        let evict_result = evict_stale_checked(&mut registry, current_timestamp);
+        assert_eq!(evict_result.results.len(), 0);
+        assert_eq!(evict_result.key_tombstones.len(), 1);
+        let key_tombstone = evict_result.key_tombstones[0].clone();
+        assert_eq!(key_tombstone.tombstone.key_id, key_id);
+        assert!(key_tombstone.transfers.is_empty());
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(2);
+        let result = registry.republish_key_tombstone_cp(
+            key_tombstone.tombstone,
+            key_tombstone.transfers,
+            current_timestamp,
+            cp.get(),
+        );
+        assert!(result.success, "{}", result.error);
+
+        let evict_result = evict_stale_checked(&mut registry, current_timestamp + 10);
+        assert_eq!(evict_result.key_tombstones.len(), 0);
+
+        // key tombstones outlive `expired_timeout` until `tombstone_retention` passes
+        let expired_timestamp = timestamp_created + DEFAULT_EXPIRED_AGE;
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(0);
+        let result = registry.clear_expired_cp(expired_timestamp, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.count_keys, 0);
+        assert_eq!(result.count_key_tombstones, 0);
+
+        let result =
+            registry.clear_expired_cp(timestamp_created + DEFAULT_TOMBSTONE_RETENTION, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.count_key_tombstones, 1);
+    }
+
+    fn register_key_with_challenge_checked(
+        registry: &mut ServiceInterface,
+        kp: &KeyPair,
+        challenge: Vec<u8>,
+        challenge_type: &str,
+    ) -> String {
+        let result = register_key_with_challenge(
+            registry,
+            kp,
+            "some_key".to_string(),
+            100u64,
+            150u64,
+            0,
+            challenge,
+            challenge_type.to_string(),
+        );
+        assert!(result.success, "{}", result.error);
+        result.key_id
+    }
+
+    fn put_record_with_solution(
+        registry: &mut ServiceInterface,
+        key_id: String,
+        issuer_kp: &KeyPair,
+        solution: Vec<u8>,
+    ) -> RegistryResult {
+        let host_kp = KeyPair::generate_ed25519();
+        put_record(
+            registry,
+            key_id,
+            issuer_kp,
+            &host_kp,
+            100u64,
+            150u64,
+            "some_record_value".to_string(),
+            vec![],
+            vec![],
+            solution,
+            0,
+        )
+    }
+
+    #[test]
+    fn register_key_unknown_challenge_type() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+
+        let result = register_key_with_challenge(
+            &mut registry,
+            &kp,
+            "some_key".to_string(),
+            100u64,
+            150u64,
+            0,
+            vec![],
+            "unknown".to_string(),
+        );
+        assert_eq!(
+            result.error,
+            UnknownChallengeType("unknown".to_string()).to_string()
+        );
+    }
+
+    #[test]
+    fn owner_challenge_permissions() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+
+        let key_id = register_key_with_challenge_checked(&mut registry, &owner_kp, vec![], "owner");
+
+        let result = put_record_with_solution(&mut registry, key_id.clone(), &owner_kp, vec![]);
+        assert!(result.success, "{}", result.error);
+
+        let result = put_record_with_solution(&mut registry, key_id.clone(), &other_kp, vec![]);
+        assert!(!result.success);
+        assert!(
+            result.error.contains("has no permission"),
+            "{}",
+            result.error
+        );
+
+        let result = add_tombstone(
+            &mut registry,
+            key_id,
+            "some_peer_id".to_string(),
+            &other_kp,
+            150u64,
+            vec![],
+        );
+        assert!(!result.success);
+        assert!(
+            result.error.contains("has no permission"),
+            "{}",
+            result.error
+        );
+    }
+
+    #[test]
+    fn allowlist_challenge_permissions() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let allowed_kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+        let allowlist = format!("some_peer_id,{}", allowed_kp.get_peer_id().to_base58());
+
+        let key_id = register_key_with_challenge_checked(
+            &mut registry,
+            &owner_kp,
+            allowlist.into_bytes(),
+            "allowlist",
+        );
+
+        let result = put_record_with_solution(&mut registry, key_id.clone(), &owner_kp, vec![]);
+        assert!(result.success, "{}", result.error);
+
+        let result = put_record_with_solution(&mut registry, key_id.clone(), &allowed_kp, vec![]);
+        assert!(result.success, "{}", result.error);
+
+        let result = put_record_with_solution(&mut registry, key_id, &other_kp, vec![]);
+        assert!(!result.success);
+        assert!(
+            result.error.contains("has no permission"),
+            "{}",
+            result.error
+        );
+    }
+
+    #[test]
+    fn delegation_challenge_permissions() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let delegate_kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+
+        let key_id =
+            register_key_with_challenge_checked(&mut registry, &owner_kp, vec![], "delegation");
+
+        let delegation_bytes =
+            registry.get_delegation_bytes(key_id.clone(), delegate_kp.get_peer_id().to_base58());
+        let solution = owner_kp.sign(&delegation_bytes).unwrap().to_vec().to_vec();
+
+        let result = put_record_with_solution(
+            &mut registry,
+            key_id.clone(),
+            &delegate_kp,
+            solution.clone(),
+        );
+        assert!(result.success, "{}", result.error);
+
+        // delegation is bound to the delegate peer id
+        let result = put_record_with_solution(&mut registry, key_id.clone(), &other_kp, solution);
+        assert!(!result.success);
+        assert!(
+            result.error.contains("has no permission"),
+            "{}",
+            result.error
+        );
+
+        let result = put_record_with_solution(&mut registry, key_id, &delegate_kp, vec![]);
+        assert!(!result.success);
+    }
+
+    fn transfer_key(
+        registry: &mut ServiceInterface,
+        key_id: String,
+        owner_kp: &KeyPair,
+        new_owner_peer_id: String,
+        timestamp_issued: u64,
+    ) -> RegistryResult {
+        let issuer_peer_id = owner_kp.get_peer_id().to_base58();
+        let transfer_bytes = registry.get_key_transfer_bytes(
+            key_id.clone(),
+            vec![issuer_peer_id.clone()],
+            new_owner_peer_id.clone(),
+            timestamp_issued,
+        );
+        let signature = owner_kp.sign(&transfer_bytes).unwrap().to_vec().to_vec();
+        let cp = CPWrapper::new(&issuer_peer_id, "host_id").add_timestamp_tetraplets(5);
+        registry.transfer_key_cp(
+            key_id,
+            vec![issuer_peer_id],
+            new_owner_peer_id,
+            timestamp_issued,
+            signature,
+            150u64,
+            cp.get(),
+        )
+    }
+
+    /// register a new version of the key originally owned by `owner_peer_id`, signed by `signer_kp`
+    fn register_key_signed_by(
+        registry: &mut ServiceInterface,
+        signer_kp: &KeyPair,
+        owner_peer_id: String,
+        timestamp_created: u64,
+    ) -> RegisterKeyResult {
+        let label = "some_key".to_string();
+        let key_bytes = registry.get_key_bytes(
+            label.clone(),
+            vec![owner_peer_id.clone()],
+            timestamp_created,
+            vec![],
+            "owner".to_string(),
+        );
+        let signature = signer_kp.sign(&key_bytes).unwrap().to_vec().to_vec();
+        let cp = CPWrapper::new(&signer_kp.get_peer_id().to_base58(), "host_id")
+            .add_weight_tetraplets(6)
+            .add_timestamp_tetraplets(7);
+        let weight = get_weight(owner_peer_id.clone(), 0);
+        registry.register_key_cp(
+            label,
+            vec![owner_peer_id],
+            timestamp_created,
+            vec![],
+            "owner".to_string(),
+            signature,
+            weight,
+            150u64,
+            cp.get(),
+        )
+    }
+
+    #[test]
+    fn transfer_key_to_new_owner() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let new_owner_kp = KeyPair::generate_ed25519();
+        let owner_peer_id = owner_kp.get_peer_id().to_base58();
+        let new_owner_peer_id = new_owner_kp.get_peer_id().to_base58();
+
+        let key_id = register_key_with_challenge_checked(&mut registry, &owner_kp, vec![], "owner");
+
+        // only the current owner can transfer the key
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &new_owner_kp,
+            new_owner_peer_id.clone(),
+            120u64,
+        );
+        assert!(!result.success);
+        assert!(
+            result.error.contains("but the key is owned by"),
+            "{}",
+            result.error
+        );
+
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &owner_kp,
+            new_owner_peer_id.clone(),
+            120u64,
+        );
+        assert!(result.success, "{}", result.error);
+
+        let cp = CPWrapper::new("peer_id", "host_id");
+        let result = registry.get_key_transfer_cp(key_id.clone(), cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.transfer.len(), 1);
+        assert_eq!(result.transfer[0].new_owner_peer_id, new_owner_peer_id);
+
+        // the old owner can't issue new versions of the key or transfer it again
+        let result =
+            register_key_signed_by(&mut registry, &owner_kp, owner_peer_id.clone(), 130u64);
+        assert!(!result.success);
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &owner_kp,
+            owner_peer_id.clone(),
+            130u64,
+        );
+        assert!(!result.success);
+
+        let result =
+            register_key_signed_by(&mut registry, &new_owner_kp, owner_peer_id.clone(), 130u64);
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.key_id, key_id);
+
+        // permissions follow the new owner
+        let result = put_record_with_solution(&mut registry, key_id.clone(), &new_owner_kp, vec![]);
+        assert!(result.success, "{}", result.error);
+        let result = put_record_with_solution(&mut registry, key_id, &owner_kp, vec![]);
+        assert!(!result.success);
+        assert!(
+            result.error.contains("has no permission"),
+            "{}",
+            result.error
+        );
+    }
+
+    #[test]
+    fn republish_transferred_key() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let new_owner_kp = KeyPair::generate_ed25519();
+        let owner_peer_id = owner_kp.get_peer_id().to_base58();
+
+        let key_id = register_key_with_challenge_checked(&mut registry, &owner_kp, vec![], "owner");
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &owner_kp,
+            new_owner_kp.get_peer_id().to_base58(),
+            120u64,
+        );
+        assert!(result.success, "{}", result.error);
+        let result =
+            register_key_signed_by(&mut registry, &new_owner_kp, owner_peer_id.clone(), 130u64);
+        assert!(result.success, "{}", result.error);
+
+        let key = get_key_metadata(&mut registry, key_id);
+        let evict_result = evict_stale_checked(&mut registry, 150u64 + DEFAULT_STALE_AGE);
+        assert_eq!(evict_result.results.len(), 1);
+        assert_eq!(evict_result.results[0].transfers.len(), 1);
+        let transfers = evict_result.results[0].transfers.clone();
+
+        // another peer doesn't know about the transfer
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let cp = CPWrapper::new(&owner_peer_id, "host_id")
+            .add_weight_tetraplets(1)
+            .add_timestamp_tetraplets(2);
+        let weight = get_weight(owner_peer_id.clone(), 0);
+        let result = registry.republish_key_cp(key.clone(), weight.clone(), 150u64, cp.get());
+        assert!(!result.success);
+
+        let cp = CPWrapper::new(&owner_peer_id, "host_id")
+            .add_weight_tetraplets(2)
+            .add_timestamp_tetraplets(3);
+        let result =
+            registry.republish_key_transfer_cp(key.clone(), transfers, weight, 150u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(get_key_metadata(&mut registry, key.id.clone()), key);
+    }
+
+    #[test]
+    fn republish_twice_transferred_key() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let second_owner_kp = KeyPair::generate_ed25519();
+        let third_owner_kp = KeyPair::generate_ed25519();
+        let owner_peer_id = owner_kp.get_peer_id().to_base58();
+        let second_owner_peer_id = second_owner_kp.get_peer_id().to_base58();
+        let third_owner_peer_id = third_owner_kp.get_peer_id().to_base58();
+
+        let key_id = register_key_with_challenge_checked(&mut registry, &owner_kp, vec![], "owner");
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &owner_kp,
+            second_owner_peer_id.clone(),
+            120u64,
+        );
+        assert!(result.success, "{}", result.error);
+        let result = register_key_signed_by(
+            &mut registry,
+            &second_owner_kp,
+            owner_peer_id.clone(),
+            130u64,
+        );
+        assert!(result.success, "{}", result.error);
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &second_owner_kp,
+            third_owner_peer_id.clone(),
+            140u64,
+        );
+        assert!(result.success, "{}", result.error);
+        let result = register_key_signed_by(
+            &mut registry,
+            &third_owner_kp,
+            owner_peer_id.clone(),
+            145u64,
+        );
+        assert!(result.success, "{}", result.error);
+
+        let key = get_key_metadata(&mut registry, key_id.clone());
+        let evict_result = evict_stale_checked(&mut registry, 150u64 + DEFAULT_STALE_AGE);
+        assert_eq!(evict_result.results.len(), 1);
+        let transfers = evict_result.results[0].transfers.clone();
+        assert_eq!(transfers.len(), 2);
+        assert_eq!(transfers[0].new_owner_peer_id, second_owner_peer_id);
+        assert_eq!(transfers[1].new_owner_peer_id, third_owner_peer_id);
+
+        // an empty peer can't verify the last link without the first one
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let cp = CPWrapper::new(&owner_peer_id, "host_id")
+            .add_weight_tetraplets(2)
+            .add_timestamp_tetraplets(3);
+        let weight = get_weight(owner_peer_id.clone(), 0);
+        let result = registry.republish_key_transfer_cp(
+            key.clone(),
+            vec![transfers[1].clone()],
+            weight.clone(),
+            150u64,
+            cp.get(),
+        );
+        assert_eq!(
+            result.error,
+            KeyTransferNotByOwner(key_id.clone(), second_owner_peer_id, owner_peer_id).to_string()
+        );
+
+        let result =
+            registry.republish_key_transfer_cp(key.clone(), transfers, weight, 150u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(get_key_metadata(&mut registry, key_id.clone()), key);
+
+        let cp = CPWrapper::new("peer_id", "host_id");
+        let result = registry.get_key_transfer_cp(key_id, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.transfer[0].new_owner_peer_id, third_owner_peer_id);
+    }
+
+    #[test]
+    fn republish_key_tombstone_of_transferred_key() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let new_owner_kp = KeyPair::generate_ed25519();
+        let owner_peer_id = owner_kp.get_peer_id().to_base58();
+        let new_owner_peer_id = new_owner_kp.get_peer_id().to_base58();
+        let label = "some_key".to_string();
+
+        let key_id = register_key_with_challenge_checked(&mut registry, &owner_kp, vec![], "owner");
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &owner_kp,
+            new_owner_peer_id.clone(),
+            120u64,
+        );
+        assert!(result.success, "{}", result.error);
+
+        // the former owner can't remove the transferred key
+        let signature = get_signed_key_tombstone_bytes(
+            &mut registry,
+            &owner_kp,
+            label.clone(),
+            owner_peer_id.clone(),
+            140u64,
+        );
+        let cp = CPWrapper::new(&owner_peer_id, "host_id").add_timestamp_tetraplets(4);
+        let result = registry.add_key_tombstone_cp(
+            label.clone(),
+            vec![owner_peer_id.clone()],
+            140u64,
+            signature.clone(),
+            150u64,
+            cp.get(),
+        );
+        assert!(!result.success);
+
+        let new_owner_signature = get_signed_key_tombstone_bytes(
+            &mut registry,
+            &new_owner_kp,
+            label.clone(),
+            owner_peer_id.clone(),
+            140u64,
+        );
+        let cp = CPWrapper::new(&new_owner_peer_id, "host_id").add_timestamp_tetraplets(4);
+        let result = registry.add_key_tombstone_cp(
+            label,
+            vec![owner_peer_id],
+            140u64,
+            new_owner_signature,
+            150u64,
+            cp.get(),
+        );
+        assert!(result.success, "{}", result.error);
+
+        let evict_result = evict_stale_checked(&mut registry, 150u64 + DEFAULT_STALE_AGE);
+        assert_eq!(evict_result.key_tombstones.len(), 1);
+        let key_tombstone = evict_result.key_tombstones[0].clone();
+        assert_eq!(key_tombstone.transfers.len(), 1);
+
+        // another peer doesn't hold the key
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(2);
+
+        let result = registry.republish_key_tombstone_cp(
+            key_tombstone.tombstone.clone(),
+            vec![],
+            150u64,
+            cp.get(),
+        );
+        assert_eq!(
+            result.error,
+            KeyTombstoneSignerUnknown(key_id.clone()).to_string()
+        );
+
+        let mut former_owner_tombstone = key_tombstone.tombstone.clone();
+        former_owner_tombstone.signature = signature;
+        let result = registry.republish_key_tombstone_cp(
+            former_owner_tombstone,
+            key_tombstone.transfers.clone(),
+            150u64,
+            cp.get(),
+        );
+        assert!(!result.success);
+        let result = registry.get_key_tombstone_cp(key_id.clone(), cp.get());
+        assert!(!result.success);
+
+        let result = registry.republish_key_tombstone_cp(
+            key_tombstone.tombstone.clone(),
+            key_tombstone.transfers,
+            150u64,
+            cp.get(),
+        );
+        assert!(result.success, "{}", result.error);
+        let result = registry.get_key_tombstone_cp(key_id, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(
+            result.tombstone.signature,
+            key_tombstone.tombstone.signature
+        );
+    }
+
+    fn get_keys_by_owner_checked(
+        registry: &mut ServiceInterface,
+        owner_peer_id: String,
+        cursor: String,
+        limit: u64,
+    ) -> (Vec<Key>, String) {
+        let cp = CPWrapper::new("peer_id", "host_id");
+        let result = registry.get_keys_by_owner_cp(owner_peer_id, cursor, limit, cp.get());
+        assert!(result.success, "{}", result.error);
+        (result.keys, result.next_cursor)
+    }
+
+    #[test]
+    fn get_keys_by_owner_paginated() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+        let owner_peer_id = owner_kp.get_peer_id().to_base58();
+
+        let mut key_ids: Vec<String> = (0..5)
+            .map(|i| register_key_checked(&mut registry, &owner_kp, f!("key_{i}"), 100, 150, 0))
+            .collect();
+        key_ids.sort();
+        register_key_checked(&mut registry, &other_kp, "key_0".to_string(), 100, 150, 0);
+
+        let mut listed = vec![];
+        let mut cursor = "".to_string();
+        loop {
+            let (keys, next_cursor) =
+                get_keys_by_owner_checked(&mut registry, owner_peer_id.clone(), cursor, 2);
+            assert!(keys.len() <= 2);
+            listed.extend(keys.into_iter().map(|k| k.id));
+            if next_cursor.is_empty() {
+                break;
+            }
+            cursor = next_cursor;
+        }
+        assert_eq!(listed, key_ids);
+
+        let (keys, next_cursor) =
+            get_keys_by_owner_checked(&mut registry, "some_peer_id".to_string(), "".to_string(), 0);
+        assert!(keys.is_empty());
+        assert!(next_cursor.is_empty());
+    }
+
+    #[test]
+    fn get_keys_by_owner_after_transfer() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let owner_kp = KeyPair::generate_ed25519();
+        let new_owner_kp = KeyPair::generate_ed25519();
+        let owner_peer_id = owner_kp.get_peer_id().to_base58();
+        let new_owner_peer_id = new_owner_kp.get_peer_id().to_base58();
+
+        let key_id = register_key_checked(
+            &mut registry,
+            &owner_kp,
+            "some_key".to_string(),
+            100,
+            150,
+            0,
+        );
+        let result = transfer_key(
+            &mut registry,
+            key_id.clone(),
+            &owner_kp,
+            new_owner_peer_id.clone(),
+            120u64,
+        );
+        assert!(result.success, "{}", result.error);
+
+        let (keys, _) = get_keys_by_owner_checked(&mut registry, owner_peer_id, "".to_string(), 0);
+        assert!(keys.is_empty());
+        let (keys, _) =
+            get_keys_by_owner_checked(&mut registry, new_owner_peer_id, "".to_string(), 0);
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].id, key_id);
+    }
+
+    fn search_keys_checked(
+        registry: &mut ServiceInterface,
+        query: &str,
+        match_type: &str,
+    ) -> Vec<String> {
+        let cp = CPWrapper::new("peer_id", "host_id");
+        let result = registry.search_keys_cp(
+            query.to_string(),
+            match_type.to_string(),
+            "".to_string(),
+            0,
+            cp.get(),
+        );
+        assert!(result.success, "{}", result.error);
+        let mut labels: Vec<String> = result.keys.into_iter().map(|k| k.key.label).collect();
+        labels.sort();
+        labels
+    }
+
+    #[test]
+    fn search_keys_by_label() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        for label in ["chat-a", "chat-b", "chatroom", "other-chat"] {
+            register_key_checked(&mut registry, &kp, label.to_string(), 100, 150, 0);
+        }
+
+        assert_eq!(
+            search_keys_checked(&mut registry, "chat-a", "exact"),
+            vec!["chat-a"]
+        );
+        assert_eq!(
+            search_keys_checked(&mut registry, "chat-", "prefix"),
+            vec!["chat-a", "chat-b"]
+        );
+        assert_eq!(
+            search_keys_checked(&mut registry, "chat", "substring"),
+            vec!["chat-a", "chat-b", "chatroom", "other-chat"]
+        );
+        assert!(search_keys_checked(&mut registry, "chat", "exact").is_empty());
+
+        let cp = CPWrapper::new("peer_id", "host_id");
+        let result = registry.search_keys_cp(
+            "chat".to_string(),
+            "regex".to_string(),
+            "".to_string(),
+            0,
+            cp.get(),
+        );
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            UnknownLabelMatchType("regex".to_string()).to_string()
+        );
+    }
+
+    #[test]
+    fn get_records_by_service_peer_and_issuer() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let other_host_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let other_key_id =
+            register_key_checked(&mut registry, &kp, "other_key".to_string(), 100, 150, 0);
+
+        for (key_id, issuer_kp, host_kp, service_id) in [
+            (&key_id, &kp, &host_kp, "some_service"),
+            (&other_key_id, &kp, &other_host_kp, "some_service"),
+            (&other_key_id, &other_kp, &host_kp, "other_service"),
+        ] {
+            let result = put_record(
+                &mut registry,
+                key_id.clone(),
+                issuer_kp,
+                host_kp,
+                100u64,
+                150u64,
+                "some_value".to_string(),
+                vec![],
+                vec![service_id.to_string()],
+                vec![],
+                0,
+            );
+            assert!(result.success, "{}", result.error);
+        }
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result =
+            registry.get_records_by_service_id_cp("some_service".to_string(), 150u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.result.len(), 2);
+
+        let result =
+            registry.get_records_by_peer_id_cp(host_kp.get_peer_id().to_base58(), 150u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.result.len(), 2);
+
+        let result =
+            registry.get_records_by_issuer_cp(other_kp.get_peer_id().to_base58(), 150u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.result.len(), 1);
+        assert_eq!(result.result[0].metadata.key_id, other_key_id);
+
+        // expired records are excluded
+        let result = registry.get_records_by_service_id_cp(
+            "some_service".to_string(),
+            150u64 + DEFAULT_EXPIRED_AGE,
+            cp.get(),
+        );
+        assert!(result.success, "{}", result.error);
+        assert!(result.result.is_empty());
+    }
+
+    fn create_signed_record(
+        registry: &mut ServiceInterface,
+        key_id: String,
+        issuer_kp: &KeyPair,
+        host_kp: &KeyPair,
+        timestamp_created: u64,
+    ) -> Record {
+        create_signed_record_with_ttl(
+            registry,
+            key_id,
+            issuer_kp,
+            host_kp,
+            timestamp_created,
+            vec![],
+        )
+    }
+
+    fn create_signed_record_with_ttl(
+        registry: &mut ServiceInterface,
+        key_id: String,
+        issuer_kp: &KeyPair,
+        host_kp: &KeyPair,
+        timestamp_created: u64,
+        ttl: Vec<u64>,
+    ) -> Record {
+        let metadata = create_record_metadata(
+            registry,
+            key_id,
+            issuer_kp,
+            timestamp_created,
+            "some_value".to_string(),
+            host_kp.get_peer_id().to_base58(),
+            vec![],
+            vec![],
+            vec![],
+            ttl,
+        );
+        let signature =
+            get_signed_record_bytes(registry, host_kp, metadata.clone(), timestamp_created);
+        Record {
+            metadata,
+            timestamp_created,
+            signature,
+        }
+    }
+
+    #[test]
+    fn put_records_batch() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let issuer_peer_id = kp.get_peer_id().to_base58();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let other_key_id =
+            register_key_checked(&mut registry, &kp, "other_key".to_string(), 100, 150, 0);
+
+        let records = vec![
+            create_signed_record(&mut registry, key_id.clone(), &kp, &host_kp, 150u64),
+            create_signed_record(
+                &mut registry,
+                "missing_key_id".to_string(),
+                &kp,
+                &host_kp,
+                150u64,
+            ),
+            create_signed_record(&mut registry, other_key_id.clone(), &kp, &host_kp, 150u64),
+        ];
+        let weights = vec![get_weight(issuer_peer_id, 0); records.len()];
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, records.len())
+            .add_timestamp_tetraplets(2);
+        let result = registry.put_records_cp(records, weights, 150u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.results.len(), 3);
+        assert!(result.results[0].success, "{}", result.results[0].error);
+        assert!(!result.results[1].success);
+        assert_eq!(
+            result.results[1].error,
+            KeyNotExists("missing_key_id".to_string()).to_string()
+        );
+        assert!(result.results[2].success, "{}", result.results[2].error);
+
+        assert_eq!(get_records(&mut registry, key_id, 150u64).len(), 1);
+        assert_eq!(get_records(&mut registry, other_key_id, 150u64).len(), 1);
+    }
+
+    #[test]
+    fn put_records_missing_weight() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+
+        let records = vec![create_signed_record(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &host_kp,
+            150u64,
+        )];
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, 1)
+            .add_timestamp_tetraplets(2);
+        let result = registry.put_records_cp(records, vec![], 150u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert!(!result.results[0].success);
+        assert!(get_records(&mut registry, key_id, 150u64).is_empty());
+    }
+
+    #[test]
+    fn resolve_keys_batch() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let result = put_record_with_solution(&mut registry, key_id.clone(), &kp, vec![]);
+        assert!(result.success, "{}", result.error);
+        let result = add_tombstone(
+            &mut registry,
+            key_id.clone(),
+            host_kp.get_peer_id().to_base58(),
+            &kp,
+            150u64,
+            vec![],
+        );
+        assert!(result.success, "{}", result.error);
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.resolve_keys_cp(
+            vec![key_id.clone(), "missing_key_id".to_string()],
+            150u64,
+            cp.get(),
+        );
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.results.len(), 2);
+
+        let resolved = &result.results[0];
+        assert!(resolved.success, "{}", resolved.error);
+        assert_eq!(resolved.key.id, key_id);
+        assert_eq!(resolved.records.len(), 1);
+        assert_eq!(resolved.tombstones.len(), 1);
+
+        let resolved = &result.results[1];
+        assert!(!resolved.success);
+        assert_eq!(resolved.key_id, "missing_key_id");
+        assert_eq!(
+            resolved.error,
+            KeyNotExists("missing_key_id".to_string()).to_string()
+        );
+    }
+
+    fn create_signed_tombstone(
+        registry: &mut ServiceInterface,
+        key_id: String,
+        issuer_kp: &KeyPair,
+        peer_id: String,
+        timestamp_issued: u64,
+    ) -> Tombstone {
+        let issuer_signature = get_signed_tombstone_bytes(
+            registry,
+            key_id.clone(),
+            issuer_kp,
+            timestamp_issued,
+            peer_id.clone(),
+            vec![],
+        );
+        Tombstone {
+            key_id,
+            issued_by: issuer_kp.get_peer_id().to_base58(),
+            peer_id,
+            timestamp_issued,
+            solution: vec![],
+            issuer_signature,
+        }
+    }
+
+    /// two host key pairs ordered by peer id, records are merged and written in this order
+    fn get_ordered_host_kps() -> (KeyPair, KeyPair) {
+        let first = KeyPair::generate_ed25519();
+        let second = KeyPair::generate_ed25519();
+        if first.get_peer_id().to_base58() < second.get_peer_id().to_base58() {
+            (first, second)
+        } else {
+            (second, first)
+        }
+    }
+
+    #[test]
+    fn republish_records_is_atomic() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let issuer_peer_id = kp.get_peer_id().to_base58();
+        let (first_host_kp, second_host_kp) = get_ordered_host_kps();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+
+        // the second record is rejected because of the newer tombstone
+        add_tombstone_checked(
+            &mut registry,
+            key_id.clone(),
+            second_host_kp.get_peer_id().to_base58(),
+            &kp,
+            200u64,
+            vec![],
+        );
+
+        let records = vec![
+            create_signed_record(&mut registry, key_id.clone(), &kp, &first_host_kp, 150u64),
+            create_signed_record(&mut registry, key_id.clone(), &kp, &second_host_kp, 150u64),
+        ];
+        let weights = vec![get_weight(issuer_peer_id, 0); records.len()];
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, records.len())
+            .add_timestamp_tetraplets(2);
+        let result = registry.republish_records_cp(records, weights, 200u64, cp.get());
+        assert!(!result.success);
+
+        assert!(get_records(&mut registry, key_id, 200u64).is_empty());
+    }
+
+    #[test]
+    fn republish_tombstones_is_atomic() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        add_tombstone_checked(
+            &mut registry,
+            key_id.clone(),
+            "second_peer_id".to_string(),
+            &kp,
+            200u64,
+            vec![],
+        );
+
+        let tombstones = vec![
+            create_signed_tombstone(
+                &mut registry,
+                key_id.clone(),
+                &kp,
+                "first_peer_id".to_string(),
+                150u64,
+            ),
+            // older than the stored one
+            create_signed_tombstone(
+                &mut registry,
+                key_id.clone(),
+                &kp,
+                "second_peer_id".to_string(),
+                150u64,
+            ),
+        ];
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.republish_tombstones_cp(tombstones, 200u64, cp.get());
+        assert!(!result.success);
+
+        let tombstones = get_tombstones(&mut registry, key_id, 200u64);
+        assert_eq!(tombstones.len(), 1);
+        assert_eq!(tombstones[0].peer_id, "second_peer_id");
+    }
+
+    #[test]
+    fn put_record_eviction_is_atomic() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let issuer_peer_id = kp.get_peer_id().to_base58();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+
+        let records: Vec<Record> = (0..RECORDS_LIMIT)
+            .map(|_| {
+                let host_kp = KeyPair::generate_ed25519();
+                create_signed_record(&mut registry, key_id.clone(), &kp, &host_kp, 150u64)
+            })
+            .collect();
+        let weights = vec![get_weight(issuer_peer_id.clone(), 0); records.len()];
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, records.len())
+            .add_timestamp_tetraplets(2);
+        let result = registry.republish_records_cp(records, weights, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+
+        // the heavier record evicts the lightest one, but can't be written because of the tombstone
+        let host_kp = KeyPair::generate_ed25519();
+        add_tombstone_checked(
+            &mut registry,
+            key_id.clone(),
+            host_kp.get_peer_id().to_base58(),
+            &kp,
+            200u64,
+            vec![],
+        );
+        let record = create_signed_record(&mut registry, key_id.clone(), &kp, &host_kp, 150u64);
+        let cp = CPWrapper::new(&issuer_peer_id, "host_id")
+            .add_weight_tetraplets(3)
+            .add_timestamp_tetraplets(4);
+        let result = registry.put_record_cp(
+            record.metadata,
+            record.timestamp_created,
+            record.signature,
+            get_weight(issuer_peer_id, 1),
+            200u64,
+            cp.get(),
+        );
+        assert!(!result.success);
+
+        assert_eq!(
+            get_records(&mut registry, key_id, 200u64).len(),
+            RECORDS_LIMIT
+        );
+    }
+
+    fn get_key_digest_checked(registry: &mut ServiceInterface, key_id: String) -> Vec<u8> {
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.get_key_digest_cp(key_id, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        result.digest
+    }
+
+    fn get_merkle_node_checked(
+        registry: &mut ServiceInterface,
+        prefix: String,
+    ) -> (MerkleNode, Vec<MerkleNode>) {
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.get_merkle_node_cp(prefix, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        (result.node, result.children)
+    }
+
+    #[test]
+    fn key_digest_is_equal_for_replicated_data() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let empty_key_digest = get_key_digest_checked(&mut registry, key_id.clone());
+
+        let record = create_signed_record(&mut registry, key_id.clone(), &kp, &host_kp, 150u64);
+        let tombstone = create_signed_tombstone(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            "some_peer_id".to_string(),
+            150u64,
+        );
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, 1)
+            .add_timestamp_tetraplets(2);
+        let weights = vec![get_weight(kp.get_peer_id().to_base58(), 0)];
+        let result = registry.republish_records_cp(vec![record.clone()], weights, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.republish_tombstones_cp(vec![tombstone.clone()], 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+
+        let digest = get_key_digest_checked(&mut registry, key_id.clone());
+        assert_ne!(digest, empty_key_digest);
+        let (root, _) = get_merkle_node_checked(&mut registry, "".to_string());
+        let key = get_key_metadata(&mut registry, key_id.clone());
+
+        // the same data with another weight on another peer
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        republish_key_checked(&mut registry, key, 200u64);
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, 1)
+            .add_timestamp_tetraplets(2);
+        let weights = vec![get_weight(kp.get_peer_id().to_base58(), 5)];
+        let result = registry.republish_records_cp(vec![record], weights, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.republish_tombstones_cp(vec![tombstone], 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+
+        assert_eq!(get_key_digest_checked(&mut registry, key_id), digest);
+        let (replicated_root, _) = get_merkle_node_checked(&mut registry, "".to_string());
+        assert_eq!(replicated_root.digest, root.digest);
+    }
+
+    #[test]
+    fn key_digest_covers_record_metadata() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let key = get_key_metadata(&mut registry, key_id.clone());
+
+        // v1 host signatures don't cover the metadata, so records with other values
+        // and the same timestamps have the same host signature
+        let host_signature = host_kp
+            .sign(&Sha256::digest(Vec::<u8>::new()))
+            .unwrap()
+            .to_vec()
+            .to_vec();
+        let mut digests = vec![];
+        for value in ["value_a", "value_b"] {
+            clear_env();
+            let mut registry = ServiceInterface::new();
+            let host_cp = CPWrapper::new("host_id", "host_id");
+            let result = registry.set_accept_v1_signatures_cp(true, host_cp.get());
+            assert!(result.success, "{}", result.error);
+            republish_key_checked(&mut registry, key.clone(), 200u64);
+            let metadata = create_record_metadata(
+                &mut registry,
+                key_id.clone(),
+                &kp,
+                150u64,
+                value.to_string(),
+                host_kp.get_peer_id().to_base58(),
+                vec![],
+                vec![],
+                vec![],
+                vec![],
+            );
+            let record = Record {
+                metadata,
+                timestamp_created: 150u64,
+                signature: host_signature.clone(),
+            };
+            let cp = CPWrapper::new("peer_id", "host_id")
+                .add_weights_tetraplets(1, 1)
+                .add_timestamp_tetraplets(2);
+            let weights = vec![get_weight(kp.get_peer_id().to_base58(), 0)];
+            let result = registry.republish_records_cp(vec![record], weights, 200u64, cp.get());
+            assert!(result.success, "{}", result.error);
+            digests.push(get_key_digest_checked(&mut registry, key_id.clone()));
+        }
+
+        assert_ne!(digests[0], digests[1]);
+    }
+
+    #[test]
+    fn key_digest_is_updated_on_writes_and_expiration() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+
+        let record = create_signed_record(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            150u64,
+        );
+        let expiring_record = create_signed_record_with_ttl(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            150u64,
+            vec![100],
+        );
+        for record in [record, expiring_record] {
+            let cp = CPWrapper::new("peer_id", "host_id")
+                .add_weights_tetraplets(1, 1)
+                .add_timestamp_tetraplets(2);
+            let weights = vec![get_weight(kp.get_peer_id().to_base58(), 0)];
+            let result = registry.republish_records_cp(vec![record], weights, 200u64, cp.get());
+            assert!(result.success, "{}", result.error);
+        }
+        let digest = get_key_digest_checked(&mut registry, key_id.clone());
+
+        // the expiring record is not replicated after its ttl
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.get_key_digest_cp(key_id.clone(), 260u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_ne!(result.digest, digest);
+        let expired_digest = result.digest;
+
+        // a new record changes the cached digest
+        let record = create_signed_record(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            160u64,
+        );
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, 1)
+            .add_timestamp_tetraplets(2);
+        let weights = vec![get_weight(kp.get_peer_id().to_base58(), 0)];
+        let result = registry.republish_records_cp(vec![record], weights, 260u64, cp.get());
+        assert!(result.success, "{}", result.error);
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.get_key_digest_cp(key_id, 260u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_ne!(result.digest, expired_digest);
+        assert_ne!(result.digest, digest);
+    }
+
+    #[test]
+    fn merkle_node_children() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let mut key_ids: Vec<String> = (0..10)
+            .map(|i| register_key_checked(&mut registry, &kp, f!("key_{i}"), 100, 150, 0))
+            .collect();
+        key_ids.sort();
+
+        let (root, children) = get_merkle_node_checked(&mut registry, "".to_string());
+        assert_eq!(root.count, 10);
+        assert_eq!(children.iter().map(|c| c.count).sum::<u64>(), 10);
+
+        for child in children {
+            let (node, _) = get_merkle_node_checked(&mut registry, child.prefix.clone());
+            assert_eq!(node.digest, child.digest);
+            assert_eq!(node.count, child.count);
+        }
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let result = registry.get_key_digests_cp("".to_string(), 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        let digest_key_ids: Vec<String> = result.digests.into_iter().map(|d| d.key_id).collect();
+        assert_eq!(digest_key_ids, key_ids);
+
+        // a new key changes the root
+        register_key_checked(&mut registry, &kp, "new_key".to_string(), 100, 150, 0);
+        let (new_root, _) = get_merkle_node_checked(&mut registry, "".to_string());
+        assert_ne!(new_root.digest, root.digest);
+    }
+
+    #[test]
+    fn diff_merkle_nodes_and_key_digests() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let key_ids: Vec<String> = (0..10)
+            .map(|i| register_key_checked(&mut registry, &kp, f!("key_{i}"), 100, 150, 0))
+            .collect();
+        let (_, children) = get_merkle_node_checked(&mut registry, "".to_string());
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let digests = registry
+            .get_key_digests_cp("".to_string(), 200u64, cp.get())
+            .digests;
+        assert!(registry
+            .diff_merkle_nodes(children.clone(), children.clone())
+            .is_empty());
+        assert!(registry
+            .diff_key_digests(digests.clone(), digests.clone())
+            .is_empty());
+
+        // one key is changed and another one is missing on the remote peer
+        let record = create_signed_record(
+            &mut registry,
+            key_ids[0].clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            150u64,
+        );
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, 1)
+            .add_timestamp_tetraplets(2);
+        let weights = vec![get_weight(kp.get_peer_id().to_base58(), 0)];
+        let result = registry.republish_records_cp(vec![record], weights, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        let new_key_id =
+            register_key_checked(&mut registry, &kp, "new_key".to_string(), 100, 150, 0);
+
+        let (_, new_children) = get_merkle_node_checked(&mut registry, "".to_string());
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(1);
+        let new_digests = registry
+            .get_key_digests_cp("".to_string(), 200u64, cp.get())
+            .digests;
+
+        let mut expected_ids = vec![key_ids[0].clone(), new_key_id];
+        expected_ids.sort();
+        assert_eq!(
+            registry.diff_key_digests(new_digests, digests),
+            expected_ids
+        );
+
+        let mut expected_prefixes: Vec<String> =
+            expected_ids.iter().map(|id| id[..1].to_string()).collect();
+        expected_prefixes.dedup();
+        assert_eq!(
+            registry.diff_merkle_nodes(new_children, children),
+            expected_prefixes
+        );
+    }
+
+    #[test]
+    fn changes_are_logged_once_in_order() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let key = get_key_metadata(&mut registry, key_id.clone());
+        republish_key_checked(&mut registry, key, 150u64);
+
+        let record = create_signed_record(&mut registry, key_id.clone(), &kp, &host_kp, 150u64);
+        for _ in 0..2 {
+            let cp = CPWrapper::new("peer_id", "host_id")
+                .add_weights_tetraplets(1, 1)
+                .add_timestamp_tetraplets(2);
+            let weights = vec![get_weight(kp.get_peer_id().to_base58(), 0)];
+            let result =
+                registry.republish_records_cp(vec![record.clone()], weights, 200u64, cp.get());
+            assert!(result.success, "{}", result.error);
+        }
+        add_tombstone_checked(
+            &mut registry,
+            key_id.clone(),
+            "some_peer_id".to_string(),
+            &kp,
+            160u64,
+            vec![],
+        );
+
+        let result = registry.get_changes(0, 100);
+        assert!(result.success, "{}", result.error);
+        let kinds: Vec<String> = result.changes.iter().map(|c| c.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                CHANGE_KEY_REGISTERED.to_string(),
+                CHANGE_RECORD_UPSERTED.to_string(),
+                CHANGE_TOMBSTONE_ADDED.to_string()
+            ]
+        );
+        assert!(result.changes.iter().all(|c| c.key_id == key_id));
+        assert_eq!(result.changes[1].peer_id, host_kp.get_peer_id().to_base58());
+        assert_eq!(result.changes[2].peer_id, "some_peer_id".to_string());
+        assert_eq!(result.last_seq, result.changes[2].seq);
+
+        // continue from the first change
+        let next = registry.get_changes(result.changes[0].seq, 1);
+        assert!(next.success, "{}", next.error);
+        assert_eq!(next.changes.len(), 1);
+        assert_eq!(next.changes[0].seq, result.changes[1].seq);
+
+        let empty = registry.get_changes(result.last_seq, 100);
+        assert!(empty.success, "{}", empty.error);
+        assert!(empty.changes.is_empty());
+    }
+
+    #[test]
+    fn expired_keys_are_logged() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let timestamp_created = 100u64;
+        let key_id = register_key_checked(
+            &mut registry,
+            &kp,
+            "some_key".to_string(),
+            timestamp_created,
+            150,
+            0,
+        );
+
+        let expired_timestamp = timestamp_created + DEFAULT_EXPIRED_AGE;
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(0);
+        let result = registry.clear_expired_cp(expired_timestamp, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.count_keys, 1);
+
+        let result = registry.get_changes(0, 100);
+        assert!(result.success, "{}", result.error);
+        let last = result.changes.last().unwrap();
+        assert_eq!(last.kind, CHANGE_EXPIRED.to_string());
+        assert_eq!(last.key_id, key_id);
+    }
+
+    #[test]
+    fn set_timeouts_requires_admin() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let admin_peer_id = KeyPair::generate_ed25519().get_peer_id().to_base58();
+
+        let cp = CPWrapper::new("some_peer_id", "host_id");
+        let result = registry.set_expired_timeout_cp(10, cp.get());
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            NotAdmin("some_peer_id".to_string()).to_string()
+        );
+        let result = registry.set_stale_timeout_cp(10, cp.get());
+        assert!(!result.success);
+
+        // only the host can configure admins
+        let cp = CPWrapper::new("some_peer_id", "host_id");
+        let result = registry.set_admins_cp(vec!["some_peer_id".to_string()], cp.get());
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            NotHost("some_peer_id".to_string()).to_string()
+        );
+
+        let cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_expired_timeout_cp(10, cp.get());
+        assert!(result.success, "{}", result.error);
+        let result = registry.set_admins_cp(vec![admin_peer_id.clone()], cp.get());
+        assert!(result.success, "{}", result.error);
+
+        let cp = CPWrapper::new(&admin_peer_id, "host_id");
+        let result = registry.set_stale_timeout_cp(10, cp.get());
+        assert!(result.success, "{}", result.error);
+        let result = registry.set_admins_cp(vec![], cp.get());
+        assert!(!result.success);
+    }
+
+    #[test]
+    fn configured_expired_timeout_is_used_consistently() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let host_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let record = create_signed_record(&mut registry, key_id.clone(), &kp, &host_kp, 150u64);
+
+        let expired_timeout = 1000u64;
+        let cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_expired_timeout_cp(expired_timeout, cp.get());
+        assert!(result.success, "{}", result.error);
+        let expired_timestamp = 150u64 + expired_timeout;
+
+        let republish = |registry: &mut ServiceInterface, current_timestamp: u64| {
+            let cp = CPWrapper::new("peer_id", "host_id")
+                .add_weights_tetraplets(1, 1)
+                .add_timestamp_tetraplets(2);
+            let weights = vec![get_weight(kp.get_peer_id().to_base58(), 0)];
+            registry.republish_records_cp(
+                vec![record.clone()],
+                weights,
+                current_timestamp,
+                cp.get(),
+            )
+        };
+
+        // rejected with the configured timeout, but not with the default one
+        let result = republish(&mut registry, expired_timestamp);
+        assert!(!result.success);
+        assert_eq!(result.error, RecordAlreadyExpired.to_string());
+
+        let result = republish(&mut registry, expired_timestamp - 1);
+        assert!(result.success, "{}", result.error);
+        assert_eq!(
+            get_records(&mut registry, key_id.clone(), expired_timestamp - 1).len(),
+            1
+        );
+        assert!(get_records(&mut registry, key_id, expired_timestamp).is_empty());
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(0);
+        let result = registry.clear_expired_cp(expired_timestamp, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.count_records, 1);
+    }
+
+    #[test]
+    fn record_ttl_is_respected() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let issuer_peer_id = kp.get_peer_id().to_base58();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+
+        let republish = |registry: &mut ServiceInterface, records: Vec<Record>, current: u64| {
+            let weights = vec![get_weight(issuer_peer_id.clone(), 0); records.len()];
+            let cp = CPWrapper::new("peer_id", "host_id")
+                .add_weights_tetraplets(1, records.len())
+                .add_timestamp_tetraplets(2);
+            registry.republish_records_cp(records, weights, current, cp.get())
+        };
+
+        let short_lived = create_signed_record_with_ttl(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            150u64,
+            vec![100],
+        );
+        let default = create_signed_record(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            150u64,
+        );
+        let long_lived = create_signed_record_with_ttl(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            150u64,
+            vec![u32::MAX as u64],
+        );
+        let records = vec![short_lived.clone(), default, long_lived];
+        let result = republish(&mut registry, records, 200u64);
+        assert!(result.success, "{}", result.error);
+
+        assert_eq!(get_records(&mut registry, key_id.clone(), 249u64).len(), 3);
+        let records = get_records(&mut registry, key_id.clone(), 250u64);
+        assert_eq!(records.len(), 2);
+        assert!(records
+            .iter()
+            .all(|r| r.metadata.peer_id != short_lived.metadata.peer_id));
+
+        let result = republish(&mut registry, vec![short_lived], 250u64);
+        assert!(!result.success);
+        assert_eq!(result.error, RecordAlreadyExpired.to_string());
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(0);
+        let result = registry.clear_expired_cp(250u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.count_records, 1);
+
+        // ttl is capped by the configured maximum
+        let cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_max_record_ttl_cp(1000, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(get_records(&mut registry, key_id.clone(), 1149u64).len(), 2);
+        assert_eq!(get_records(&mut registry, key_id.clone(), 1150u64).len(), 1);
+
+        let invalid = create_signed_record_with_ttl(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            &KeyPair::generate_ed25519(),
+            150u64,
+            vec![0],
+        );
+        let result = republish(&mut registry, vec![invalid], 200u64);
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            InvalidRecordTtl(key_id, issuer_peer_id.clone()).to_string()
+        );
+    }
+
+    #[test]
+    fn expired_tombstones_are_removed() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        add_tombstone_checked(
+            &mut registry,
+            key_id.clone(),
+            "some_peer_id".to_string(),
+            &kp,
+            160u64,
+            vec![],
+        );
+
+        let host_cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_tombstone_retention_cp(1000, host_cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(
+            get_tombstones(&mut registry, key_id.clone(), 1159u64).len(),
+            1
+        );
+        assert!(get_tombstones(&mut registry, key_id.clone(), 1160u64).is_empty());
+
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(0);
+        let result = registry.clear_expired_cp(1159u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.count_tombstones, 0);
+        let result = registry.clear_expired_cp(1160u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(result.count_tombstones, 1);
+
+        // the tombstone is removed, not just hidden
+        let result = registry.set_tombstone_retention_cp(DEFAULT_EXPIRED_AGE, host_cp.get());
+        assert!(result.success, "{}", result.error);
+        assert!(get_tombstones(&mut registry, key_id, 1160u64).is_empty());
+    }
+
+    #[test]
+    fn config_is_validated() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let config = registry.get_config();
+        assert!(config.success, "{}", config.error);
+        assert_eq!(config.expired_timeout, DEFAULT_EXPIRED_AGE);
+        assert_eq!(config.stale_timeout, DEFAULT_STALE_AGE);
+
+        let cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_stale_timeout_cp(DEFAULT_EXPIRED_AGE, cp.get());
+        assert!(!result.success);
+        let result = registry.set_expired_timeout_cp(0, cp.get());
+        assert!(!result.success);
+        assert_eq!(
+            result.error,
+            InvalidConfig("expired_timeout should be greater than zero".to_string()).to_string()
+        );
+        assert_eq!(registry.get_config().stale_timeout, DEFAULT_STALE_AGE);
+
+        // missing fields are filled with defaults
+        fs::write(CONFIG_FILE, "stale_timeout = 100").unwrap();
+        let config = registry.get_config();
+        assert!(config.success, "{}", config.error);
+        assert_eq!(config.stale_timeout, 100);
+        assert_eq!(config.expired_timeout, DEFAULT_EXPIRED_AGE);
+        assert!(config.admins.is_empty());
+
+        // an invalid config can be fixed by the setters
+        fs::write(CONFIG_FILE, f!("stale_timeout = {DEFAULT_EXPIRED_AGE}")).unwrap();
+        assert!(!registry.get_config().success);
+        let cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_stale_timeout_cp(100, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert_eq!(registry.get_config().stale_timeout, 100);
+
+        // a corrupted config is reported instead of a panic
+        fs::write(CONFIG_FILE, "stale_timeout = \"100\"").unwrap();
+        let config = registry.get_config();
+        assert!(!config.success);
+        let cp = CPWrapper::new("peer_id", "host_id").add_timestamp_tetraplets(0);
+        let result = registry.clear_expired_cp(100u64, cp.get());
+        assert!(!result.success);
+    }
+
+    fn put_weighted_records(
+        registry: &mut ServiceInterface,
+        records: Vec<(Record, u32)>,
+    ) -> Vec<RegistryResult> {
+        let weights = records
+            .iter()
+            .map(|(record, weight)| get_weight(record.metadata.issued_by.clone(), *weight))
+            .collect();
+        let records: Vec<Record> = records.into_iter().map(|(record, _)| record).collect();
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, records.len())
+            .add_timestamp_tetraplets(2);
+        let result = registry.put_records_cp(records, weights, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        result.results
+    }
+
+    #[test]
+    fn records_limit_is_configurable_per_key() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let owner_peer_id = kp.get_peer_id().to_base58();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let host_kps: Vec<KeyPair> = (0..3).map(|_| KeyPair::generate_ed25519()).collect();
+
+        let host_cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_records_limit_cp(2, host_cp.get());
+        assert!(result.success, "{}", result.error);
+
+        let mut records: Vec<(Record, u32)> = host_kps
+            .iter()
+            .map(|host_kp| {
+                let record =
+                    create_signed_record(&mut registry, key_id.clone(), &kp, host_kp, 150u64);
+                (record, 1)
+            })
+            .collect();
+        records[2].1 = 0;
+        let results = put_weighted_records(&mut registry, records.clone());
+        assert!(results[0].success && results[1].success);
+        assert_eq!(
+            results[2].error,
+            ValuesLimitExceeded(key_id.clone()).to_string()
+        );
+
+        // only the owner can change the limit of the key
+        let cp = CPWrapper::new("some_peer_id", "host_id");
+        let result = registry.set_key_records_limit_cp(key_id.clone(), 3, cp.get());
+        assert_eq!(
+            result.error,
+            NotKeyOwner(key_id.clone(), "some_peer_id".to_string()).to_string()
+        );
+        let cp = CPWrapper::new(&owner_peer_id, "host_id");
+        let result = registry.set_key_records_limit_cp(key_id.clone(), 1025, cp.get());
+        assert_eq!(result.error, RecordsLimitTooLarge(1025, 1024).to_string());
+        let result = registry.set_key_records_limit_cp(key_id.clone(), 3, cp.get());
+        assert!(result.success, "{}", result.error);
+
+        let results = put_weighted_records(&mut registry, vec![records[2].clone()]);
+        assert!(results[0].success, "{}", results[0].error);
+        assert_eq!(get_records(&mut registry, key_id.clone(), 200u64).len(), 3);
+
+        // updates of stored records don't evict anything
+        let updated =
+            create_signed_record(&mut registry, key_id.clone(), &kp, &host_kps[0], 160u64);
+        let results = put_weighted_records(&mut registry, vec![(updated, 0)]);
+        assert!(results[0].success, "{}", results[0].error);
+        assert_eq!(get_records(&mut registry, key_id, 200u64).len(), 3);
+    }
+
+    #[test]
+    fn eviction_policies() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let other_kp = KeyPair::generate_ed25519();
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        let other_key_id =
+            register_key_checked(&mut registry, &kp, "other_key".to_string(), 100, 150, 0);
+
+        let host_cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_records_limit_cp(2, host_cp.get());
+        assert!(result.success, "{}", result.error);
+        let result = registry.set_eviction_policy_cp("random".to_string(), host_cp.get());
+        assert_eq!(
+            result.error,
+            UnknownEvictionPolicy("random".to_string()).to_string()
+        );
+
+        // the oldest record is evicted regardless of weights
+        let result = registry.set_eviction_policy_cp("recency".to_string(), host_cp.get());
+        assert!(result.success, "{}", result.error);
+        let oldest_host_kp = KeyPair::generate_ed25519();
+        let records = vec![
+            (
+                create_signed_record(&mut registry, key_id.clone(), &kp, &oldest_host_kp, 150u64),
+                5,
+            ),
+            (
+                create_signed_record(
+                    &mut registry,
+                    key_id.clone(),
+                    &kp,
+                    &KeyPair::generate_ed25519(),
+                    160u64,
+                ),
+                5,
+            ),
+            (
+                create_signed_record(
+                    &mut registry,
+                    key_id.clone(),
+                    &kp,
+                    &KeyPair::generate_ed25519(),
+                    170u64,
+                ),
+                0,
+            ),
+        ];
+        let results = put_weighted_records(&mut registry, records);
+        assert!(results.iter().all(|r| r.success));
+        let records = get_records(&mut registry, key_id, 200u64);
+        assert_eq!(records.len(), 2);
+        let oldest_peer_id = oldest_host_kp.get_peer_id().to_base58();
+        assert!(records.iter().all(|r| r.metadata.peer_id != oldest_peer_id));
+
+        // a record of a new issuer evicts a record of the most frequent issuer
+        let result = registry.set_eviction_policy_cp("issuer_diversity".to_string(), host_cp.get());
+        assert!(result.success, "{}", result.error);
+        let records = vec![
+            (
+                create_signed_record(
+                    &mut registry,
+                    other_key_id.clone(),
+                    &kp,
+                    &KeyPair::generate_ed25519(),
+                    150u64,
+                ),
+                5,
+            ),
+            (
+                create_signed_record(
+                    &mut registry,
+                    other_key_id.clone(),
+                    &kp,
+                    &KeyPair::generate_ed25519(),
+                    150u64,
+                ),
+                5,
+            ),
+            (
+                create_signed_record(
+                    &mut registry,
+                    other_key_id.clone(),
+                    &other_kp,
+                    &KeyPair::generate_ed25519(),
+                    150u64,
+                ),
+                0,
+            ),
+        ];
+        let results = put_weighted_records(&mut registry, records);
+        assert!(results.iter().all(|r| r.success));
+        let records = get_records(&mut registry, other_key_id, 200u64);
+        assert_eq!(records.len(), 2);
+        let other_peer_id = other_kp.get_peer_id().to_base58();
+        assert!(records
+            .iter()
+            .any(|r| r.metadata.issued_by == other_peer_id));
+    }
+
+    #[test]
+    fn write_quotas_are_enforced() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let issuer_peer_id = kp.get_peer_id().to_base58();
+
+        let host_cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_write_quotas_cp(2, 3, host_cp.get());
+        assert!(result.success, "{}", result.error);
+        let result = registry.set_trusted_quota_cp(5, 2, host_cp.get());
+        assert!(result.success, "{}", result.error);
+
+        // rejected writes are not counted
+        let result = register_key(&mut registry, &kp, "some_key".to_string(), 200, 150, 0);
+        assert!(!result.success);
+        let key_id = register_key_checked(&mut registry, &kp, "some_key".to_string(), 100, 150, 0);
+        register_key_checked(&mut registry, &kp, "other_key".to_string(), 100, 150, 0);
+        let result = register_key(&mut registry, &kp, "third_key".to_string(), 100, 150, 0);
+        assert_eq!(
+            result.error,
+            QuotaExceeded(
+                issuer_peer_id.clone(),
+                QUOTA_KIND_KEY_WRITES.to_string(),
+                2,
+                60
+            )
+            .to_string()
+        );
+        // the next window
+        register_key_checked(&mut registry, &kp, "third_key".to_string(), 100, 180, 0);
+
+        let records: Vec<Record> = (0..4)
+            .map(|_| {
+                let host_kp = KeyPair::generate_ed25519();
+                create_signed_record(&mut registry, key_id.clone(), &kp, &host_kp, 150u64)
+            })
+            .collect();
+        let cp = CPWrapper::new("peer_id", "host_id")
+            .add_weights_tetraplets(1, records.len())
+            .add_timestamp_tetraplets(2);
+        let weights = vec![get_weight(issuer_peer_id.clone(), 0); records.len()];
+        let result = registry.put_records_cp(records.clone(), weights, 200u64, cp.get());
+        assert_eq!(
+            result.error,
+            QuotaExceeded(
+                "peer_id".to_string(),
+                QUOTA_KIND_RECORD_WRITES.to_string(),
+                3,
+                60
+            )
+            .to_string()
+        );
+
+        // trusted initiators get relaxed quotas
+        let cp = CPWrapper::new(&issuer_peer_id, "host_id")
+            .add_weights_tetraplets(1, records.len())
+            .add_timestamp_tetraplets(2);
+        let weights = vec![get_weight(issuer_peer_id.clone(), 5); records.len()];
+        let result = registry.put_records_cp(records, weights, 200u64, cp.get());
+        assert!(result.success, "{}", result.error);
+        assert!(result.results.iter().all(|r| r.success));
+        assert_eq!(get_records(&mut registry, key_id.clone(), 200u64).len(), 4);
+
+        // tombstones count against the same quota with the issuer weight
+        let peer_id = "peer_id".to_string();
+        let signature = get_signed_tombstone_bytes(
+            &mut registry,
+            key_id.clone(),
+            &kp,
+            200,
+            peer_id.clone(),
+            vec![],
+        );
+        let cp = CPWrapper::new(&issuer_peer_id, &peer_id)
+            .add_weight_tetraplets(6)
+            .add_timestamp_tetraplets(7);
+        let result = registry.add_tombstone_weighted_cp(
+            key_id.clone(),
+            issuer_peer_id.clone(),
+            peer_id.clone(),
+            200,
+            vec![],
+            signature.clone(),
+            get_weight(issuer_peer_id.clone(), 0),
+            200,
+            cp.get(),
+        );
+        assert_eq!(
+            result.error,
+            QuotaExceeded(
+                issuer_peer_id.clone(),
+                QUOTA_KIND_RECORD_WRITES.to_string(),
+                3,
+                60
+            )
+            .to_string()
+        );
+        let result = registry.add_tombstone_weighted_cp(
+            key_id,
+            issuer_peer_id.clone(),
+            peer_id,
+            200,
+            vec![],
+            signature,
+            get_weight(issuer_peer_id, 5),
+            200,
+            cp.get(),
+        );
+        assert!(result.success, "{}", result.error);
+    }
+
+    fn to_hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn signature_bytes_golden_vectors() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+
+        let bytes = registry.get_key_bytes(
+            "a".repeat(300),
+            vec!["owner".to_string()],
+            100,
+            vec![1, 2],
+            "open".to_string(),
+        );
+        assert_eq!(
+            to_hex(&bytes),
+            "7efe1cbbbeede6ff0a12f28386c85d14c367987d2af85e9931f20f23094e49fc"
+        );
+
+        let metadata_bytes = registry.get_record_metadata_bytes(
+            "key".to_string(),
+            "issuer".to_string(),
+            100,
+            "v".repeat(300),
+            "peer".to_string(),
+            vec!["relay".to_string()],
+            vec![],
+            vec![],
+            vec![60],
+        );
+        assert_eq!(
+            to_hex(&metadata_bytes),
+            "e68a9462b7a21ddb9cbb42142cbaef9153df8f5a6409f8a931889693c2d63a2f"
+        );
+
+        let metadata = registry.create_record_metadata(
+            "key".to_string(),
+            "issuer".to_string(),
+            100,
+            "v".repeat(300),
+            "peer".to_string(),
+            vec!["relay".to_string()],
+            vec![],
+            vec![],
+            vec![60],
+            vec![3; 4],
+        );
+        let bytes = registry.get_record_bytes(metadata, 150);
+        assert_eq!(
+            to_hex(&bytes),
+            "7080ed7a7bed87999f3198fe1d5aec4dd2c64e98ecb85b3add06b9bf648d7143"
+        );
+
+        let bytes = registry.get_tombstone_bytes(
+            "key".to_string(),
+            "issuer".to_string(),
+            "peer".to_string(),
+            100,
+            vec![],
+        );
+        assert_eq!(
+            to_hex(&bytes),
+            "3800ccbfd1b11bf98c4a8c1011798135097b2912c0dbc307245c31942fe3c075"
+        );
+
+        let bytes =
+            registry.get_key_tombstone_bytes("label".to_string(), vec!["owner".to_string()], 100);
+        assert_eq!(
+            to_hex(&bytes),
+            "2c193be01f1aa53f171161a5f5cd5e18074c0a168a181314a246854086a43a2c"
+        );
+
+        let bytes = registry.get_key_transfer_bytes(
+            "key".to_string(),
+            vec!["owner".to_string()],
+            "new_owner".to_string(),
+            100,
+        );
+        assert_eq!(
+            to_hex(&bytes),
+            "2f8d253908e2eb2dfd8ebe5c4602f289c13e24e6d8496dc4b87c520ba6900fdf"
+        );
+
+        let bytes = registry.get_delegation_bytes("key".to_string(), "issuer".to_string());
+        assert_eq!(
+            to_hex(&bytes),
+            "51894a5e23f0faedafaed20155db9455357c9d8ed5d9aa0ac839dc36753fe53e"
+        );
+    }
+
+    #[test]
+    fn v1_signatures_are_accepted_only_if_enabled() {
+        clear_env();
+        let mut registry = ServiceInterface::new();
+        let kp = KeyPair::generate_ed25519();
+        let issuer_peer_id = kp.get_peer_id().to_base58();
+
+        let register_v1_key = |registry: &mut ServiceInterface, label: &str| {
+            let bytes = crate::key::Key::new(
+                label.to_string(),
+                issuer_peer_id.clone(),
+                100,
+                vec![],
+                "".to_string(),
+                vec![],
+            )
+            .signature_bytes_v1();
+            let cp = CPWrapper::new(&issuer_peer_id, "host_id")
+                .add_weight_tetraplets(6)
+                .add_timestamp_tetraplets(7);
+            registry.register_key_cp(
+                label.to_string(),
+                vec![issuer_peer_id.clone()],
+                100,
+                vec![],
+                "".to_string(),
+                kp.sign(&bytes).unwrap().to_vec().to_vec(),
+                get_weight(issuer_peer_id.clone(), 0),
+                150,
+                cp.get(),
+            )
+        };
+
+        // fresh configs reject v1 signatures
+        assert!(!registry.get_config().accept_v1_signatures);
+        let result = register_v1_key(&mut registry, "some_key");
+        assert!(!result.success);
+
+        let host_cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_accept_v1_signatures_cp(true, host_cp.get());
+        assert!(result.success, "{}", result.error);
+        let result = register_v1_key(&mut registry, "some_key");
+        assert!(result.success, "{}", result.error);
+
+        let result = registry.set_accept_v1_signatures_cp(false, host_cp.get());
+        assert!(result.success, "{}", result.error);
+
+        let result = register_v1_key(&mut registry, "other_key");
+        assert!(!result.success);
+        register_key_checked(&mut registry, &kp, "other_key".to_string(), 100, 150, 0);
+    }
+
+    #[test]
+    fn v1_signatures_are_accepted_in_existing_configs() {
+        clear_env();
+        // config written before accept_v1_signatures was added
+        fs::write(CONFIG_FILE, "expired_timeout = 86400\n").unwrap();
+        let mut registry = ServiceInterface::new();
+        let config = registry.get_config();
+        assert!(config.success, "{}", config.error);
+        assert!(config.accept_v1_signatures);
+    }
+
+    #[test]
+    fn paths_are_overridden_by_module_envs() {
+        // set in EnvPathsConfig.toml
+        let db_path = "/tmp/registry_env_paths.db";
+        let config_path = "/tmp/registry_env_paths_Config.toml";
+        let _ = fs::remove_file(db_path);
+        let _ = fs::remove_file(config_path);
+
+        let mut registry = marine_test_env::registry_env_paths::ServiceInterface::new();
+        let config = registry.get_config();
+        assert!(config.success, "{}", config.error);
+        assert!(fs::metadata(config_path).is_ok());
+
+        let host_cp = CPWrapper::new("host_id", "host_id");
+        let result = registry.set_stale_timeout_cp(100, host_cp.get());
+        assert!(result.success, "{}", result.error);
+        let content = fs::read_to_string(config_path).unwrap();
+        assert!(content.contains("stale_timeout = 100"), "{}", content);
+
+        let connection = Connection::open(db_path).unwrap();
+        let tables: i64 = connection
+            .query_row(
+                f!("SELECT COUNT(*) FROM sqlite_master WHERE name = '{KEYS_TABLE_NAME}'").as_str(),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tables, 1);
+    }
 }
@@ -13,13 +13,17 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use crate::config::load_config;
+use crate::defaults::QUOTA_KIND_RECORD_WRITES;
 use crate::error::ServiceError;
+use crate::misc::{check_weight_result, get_initiator_weight};
+use crate::permissions::check_permissions;
 use crate::results::{GetTombstonesResult, RegistryResult};
 use crate::storage_impl::get_storage;
-use crate::tetraplets_checkers::check_timestamp_tetraplets;
+use crate::tetraplets_checkers::{check_timestamp_tetraplets, check_weight_tetraplets};
 use crate::tombstone::Tombstone;
-use crate::wrapped_try;
-use marine_rs_sdk::marine;
+use crate::{wrapped_try, WeightResult};
+use marine_rs_sdk::{marine, CallParameters};
 
 #[marine]
 pub fn get_tombstone_bytes(
@@ -61,15 +65,78 @@ pub fn add_tombstone(
             solution,
             issuer_signature: signature,
         };
-        tombstone.verify(current_timestamp_sec)?;
+        check_and_add_tombstone(&cp, tombstone, 0, current_timestamp_sec)
+    })
+    .into()
+}
 
-        let storage = get_storage()?;
-        storage.check_key_existence(&tombstone.key_id)?;
-        storage.write_tombstone(tombstone)
+/// Same as add_tombstone, but the write quota of the issuer is relaxed by its `weight`
+#[marine]
+pub fn add_tombstone_weighted(
+    key_id: String,
+    issued_by: String,
+    peer_id: String,
+    timestamp_issued: u64,
+    solution: Vec<u8>,
+    signature: Vec<u8>,
+    weight: WeightResult,
+    current_timestamp_sec: u64,
+) -> RegistryResult {
+    wrapped_try(|| {
+        let cp = marine_rs_sdk::get_call_parameters();
+        check_weight_tetraplets(&cp, 6, 0)?;
+        check_timestamp_tetraplets(&cp, 7)?;
+        check_weight_result(&issued_by, &weight)?;
+        let tombstone = Tombstone {
+            key_id,
+            issued_by,
+            peer_id,
+            timestamp_issued,
+            solution,
+            issuer_signature: signature,
+        };
+        check_and_add_tombstone(
+            &cp,
+            tombstone,
+            get_initiator_weight(&cp.init_peer_id, &weight),
+            current_timestamp_sec,
+        )
     })
     .into()
 }
 
+/// the write is counted against the quota only if the tombstone is stored
+fn check_and_add_tombstone(
+    cp: &CallParameters,
+    tombstone: Tombstone,
+    initiator_weight: u32,
+    current_timestamp_sec: u64,
+) -> Result<(), ServiceError> {
+    let config = load_config()?;
+    let storage = get_storage()?;
+    storage.transaction(|| {
+        storage.consume_write_quota(
+            &config,
+            cp,
+            QUOTA_KIND_RECORD_WRITES,
+            1,
+            initiator_weight,
+            current_timestamp_sec,
+        )?;
+        tombstone.verify(current_timestamp_sec, config.accept_v1_signatures)?;
+
+        let key = storage.get_key(tombstone.key_id.clone())?;
+        let owner_peer_id = storage.get_key_owner(&key)?;
+        check_permissions(
+            &key,
+            &owner_peer_id,
+            &tombstone.issued_by,
+            &tombstone.solution,
+        )?;
+        storage.write_tombstone(tombstone)
+    })
+}
+
 /// Return all tombstones by key id
 #[marine]
 pub fn get_tombstones(key_id: String, current_timestamp_sec: u64) -> GetTombstonesResult {
@@ -97,9 +164,10 @@ pub fn republish_tombstones(
         let key_id = tombstones[0].key_id.clone();
         let call_parameters = marine_rs_sdk::get_call_parameters();
         check_timestamp_tetraplets(&call_parameters, 1)?;
+        let accept_v1 = load_config()?.accept_v1_signatures;
 
         for tombstone in tombstones.iter() {
-            tombstone.verify(current_timestamp_sec)?;
+            tombstone.verify(current_timestamp_sec, accept_v1)?;
 
             if tombstone.key_id != key_id {
                 return Err(ServiceError::TombstonesPublishingError);
@@ -107,12 +175,17 @@ pub fn republish_tombstones(
         }
 
         let storage = get_storage()?;
-        storage.check_key_existence(&key_id)?;
-        for tombstone in tombstones.into_iter() {
-            storage.write_tombstone(tombstone)?;
+        let key = storage.get_key(key_id)?;
+        let owner_peer_id = storage.get_key_owner(&key)?;
+        for tombstone in tombstones.iter() {
+            check_permissions(
+                &key,
+                &owner_peer_id,
+                &tombstone.issued_by,
+                &tombstone.solution,
+            )?;
         }
-
-        Ok(())
+        storage.write_tombstones(tombstones)
     })
     .into()
 }
@@ -13,9 +13,9 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
-use crate::defaults::RECORDS_TABLE_NAME;
+use crate::defaults::{CHANGE_EXPIRED, CHANGE_TOMBSTONE_ADDED, RECORDS_TABLE_NAME};
 use crate::error::ServiceError;
-use crate::load_config;
+use crate::expiry_policy::load_expiry_policy;
 use crate::storage_impl::Storage;
 use crate::tombstone::Tombstone;
 use marine_sqlite_connector::{State, Statement, Value};
@@ -41,9 +41,18 @@ impl Storage {
             tombstone.peer_id.clone(),
             tombstone.timestamp_issued,
         )?;
+        let is_stored = self.is_row_stored(
+            &tombstone.key_id,
+            &tombstone.issued_by,
+            &tombstone.peer_id,
+            true,
+            &tombstone.issuer_signature,
+            Value::Null,
+        )?;
+
         let mut statement = self.connection.prepare(f!(
             "INSERT OR REPLACE INTO {RECORDS_TABLE_NAME} VALUES (?, ?, ?, ?, ?, ?, ?, \
-            NULL, NULL, NULL, NULL, NULL, NULL);"
+            NULL, NULL, NULL, NULL, NULL, NULL, NULL);"
         ))?;
 
         let is_tombstoned = 1;
@@ -57,9 +66,29 @@ impl Storage {
 
         statement.next().map(drop)?;
 
+        if !is_stored {
+            self.log_change(
+                &tombstone.key_id,
+                CHANGE_TOMBSTONE_ADDED,
+                &tombstone.issued_by,
+                &tombstone.peer_id,
+            )?;
+        }
+
         Ok(())
     }
 
+    /// write all tombstones or none of them
+    pub fn write_tombstones(&self, tombstones: Vec<Tombstone>) -> Result<(), ServiceError> {
+        self.transaction(|| {
+            for tombstone in tombstones.into_iter() {
+                self.write_tombstone(tombstone)?;
+            }
+
+            Ok(())
+        })
+    }
+
     pub fn get_tombstones(
         &self,
         key_id: String,
@@ -70,7 +99,8 @@ impl Storage {
              FROM {RECORDS_TABLE_NAME} WHERE key_id = ? AND is_tombstoned = 1 and timestamp_issued > ?"
         ))?;
 
-        let expired_timestamp = current_timestamp_sec - load_config().expired_timeout;
+        let expired_timestamp =
+            load_expiry_policy()?.tombstone_expired_timestamp(current_timestamp_sec);
         statement.bind(1, &Value::String(key_id))?;
         statement.bind(2, &Value::Integer(expired_timestamp as i64))?;
 
@@ -83,11 +113,12 @@ impl Storage {
         Ok(result)
     }
 
-    /// Remove expired tombstones
+    /// Remove tombstones issued before or at `expired_timestamp`
     pub fn clear_expired_tombstones(&self, expired_timestamp: u64) -> Result<u64, ServiceError> {
-        self.connection.execute(f!(
-            "DELETE FROM {RECORDS_TABLE_NAME} WHERE timestamp_created <= {expired_timestamp} AND is_tombstoned = 1"
-        ))?;
+        let condition = f!("timestamp_issued <= {expired_timestamp} AND is_tombstoned = 1");
+        self.log_records_changes(&condition, CHANGE_EXPIRED)?;
+        self.connection
+            .execute(f!("DELETE FROM {RECORDS_TABLE_NAME} WHERE {condition}"))?;
         Ok(self.connection.changes() as u64)
     }
 }